@@ -0,0 +1,7 @@
+-- Add migration script here
+
+ALTER TABLE transactions
+    ADD COLUMN transfer_id UUID;
+
+CREATE INDEX idx_transactions_transfer_id ON transactions (transfer_id)
+    WHERE transfer_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Add migration script here
+CREATE INDEX idx_transactions_user_created_at
+    ON transactions (user_id, created_at DESC, id DESC);
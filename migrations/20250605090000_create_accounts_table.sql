@@ -0,0 +1,34 @@
+-- Add migration script here
+CREATE TYPE account_type AS ENUM ('checking', 'savings');
+CREATE TYPE account_status AS ENUM ('open', 'closed');
+
+CREATE TABLE accounts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    account_type account_type NOT NULL,
+    name TEXT NOT NULL,
+    status account_status NOT NULL DEFAULT 'open',
+    opened_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_accounts_user_id ON accounts (user_id);
+
+-- Every existing balance becomes that user's checking account, reusing the balance id.
+INSERT INTO accounts (id, user_id, account_type, name, opened_at)
+SELECT b.id, b.user_id, 'checking', 'Checking', u.created_at
+FROM account_balances b
+JOIN users u ON u.id = b.user_id;
+
+ALTER TABLE account_balances DROP CONSTRAINT account_balances_user_id_key;
+ALTER TABLE account_balances ADD COLUMN account_id UUID REFERENCES accounts(id) ON DELETE CASCADE;
+UPDATE account_balances SET account_id = id;
+ALTER TABLE account_balances ALTER COLUMN account_id SET NOT NULL;
+ALTER TABLE account_balances ADD CONSTRAINT account_balances_account_id_key UNIQUE (account_id);
+
+ALTER TABLE transactions ADD COLUMN account_id UUID REFERENCES accounts(id) ON DELETE CASCADE;
+UPDATE transactions t SET account_id = a.id FROM accounts a WHERE a.user_id = t.user_id;
+ALTER TABLE transactions ALTER COLUMN account_id SET NOT NULL;
+
+CREATE INDEX idx_transactions_account_created_at
+    ON transactions (account_id, created_at DESC, id DESC);
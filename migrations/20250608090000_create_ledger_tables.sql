@@ -0,0 +1,90 @@
+-- Add migration script here
+CREATE TYPE ledger_account_kind AS ENUM ('customer', 'system');
+
+CREATE TABLE ledger_accounts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    kind ledger_account_kind NOT NULL,
+    account_id UUID UNIQUE REFERENCES accounts(id) ON DELETE CASCADE,
+    code TEXT UNIQUE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Customer ledger accounts mirror an account; system accounts are addressed by code.
+    CHECK ((kind = 'customer') = (account_id IS NOT NULL)),
+    CHECK ((kind = 'system') = (code IS NOT NULL))
+);
+
+INSERT INTO ledger_accounts (kind, code, name) VALUES
+    ('system', 'cash_in', 'Cash in'),
+    ('system', 'cash_out', 'Cash out'),
+    ('system', 'fees', 'Fees');
+
+INSERT INTO ledger_accounts (kind, account_id, name)
+SELECT 'customer', id, name FROM accounts;
+
+CREATE TABLE journal_entries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE postings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    journal_entry_id UUID NOT NULL REFERENCES journal_entries(id),
+    ledger_account_id UUID NOT NULL REFERENCES ledger_accounts(id),
+    amount BIGINT NOT NULL CHECK (amount <> 0), -- positive credits, negative debits
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_postings_journal_entry_id ON postings (journal_entry_id);
+CREATE INDEX idx_postings_ledger_account_id ON postings (ledger_account_id);
+
+-- Backfill: one journal entry per transfer, or per standalone transaction.
+ALTER TABLE transactions ADD COLUMN journal_entry_id UUID REFERENCES journal_entries(id);
+
+INSERT INTO journal_entries (id, description, created_at)
+SELECT COALESCE(transfer_id, id), MIN(description), MIN(created_at)
+FROM transactions
+GROUP BY COALESCE(transfer_id, id);
+
+UPDATE transactions SET journal_entry_id = COALESCE(transfer_id, id);
+ALTER TABLE transactions ALTER COLUMN journal_entry_id SET NOT NULL;
+
+INSERT INTO postings (journal_entry_id, ledger_account_id, amount, created_at)
+SELECT t.journal_entry_id,
+       la.id,
+       CASE WHEN t.transaction_type = 'credit' THEN t.amount ELSE -t.amount END,
+       t.created_at
+FROM transactions t
+JOIN ledger_accounts la ON la.account_id = t.account_id;
+
+INSERT INTO postings (journal_entry_id, ledger_account_id, amount, created_at)
+SELECT t.journal_entry_id,
+       la.id,
+       CASE WHEN t.transaction_type = 'credit' THEN -t.amount ELSE t.amount END,
+       t.created_at
+FROM transactions t
+JOIN ledger_accounts la
+  ON la.code = CASE WHEN t.transaction_type = 'credit' THEN 'cash_in' ELSE 'cash_out' END
+WHERE t.transfer_id IS NULL;
+
+-- Every journal entry must balance by the time its transaction commits.
+CREATE FUNCTION check_journal_entry_balanced() RETURNS TRIGGER AS $$
+DECLARE
+    total BIGINT;
+BEGIN
+    SELECT COALESCE(SUM(amount), 0) INTO total
+    FROM postings
+    WHERE journal_entry_id = NEW.journal_entry_id;
+
+    IF total <> 0 THEN
+        RAISE EXCEPTION 'journal entry % is unbalanced by %', NEW.journal_entry_id, total;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER postings_balanced
+    AFTER INSERT OR UPDATE ON postings
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION check_journal_entry_balanced();
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TYPE reversal_status AS ENUM ('none', 'partially_reversed', 'reversed');
+
+ALTER TABLE transactions
+    ADD COLUMN reverses_transaction_id UUID REFERENCES transactions(id),
+    ADD COLUMN reversed_amount BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN reversal_status reversal_status NOT NULL DEFAULT 'none',
+    ADD CONSTRAINT transactions_reversed_amount_check
+        CHECK (reversed_amount >= 0 AND reversed_amount <= amount);
+
+CREATE INDEX idx_transactions_reverses_transaction_id ON transactions (reverses_transaction_id)
+    WHERE reverses_transaction_id IS NOT NULL;
@@ -0,0 +1,24 @@
+-- Add migration script here
+ALTER TABLE account_balances
+    ADD COLUMN held BIGINT NOT NULL DEFAULT 0,
+    ADD CONSTRAINT account_balances_held_check CHECK (held >= 0 AND held <= balance);
+
+CREATE TYPE hold_status AS ENUM ('pending', 'captured', 'voided', 'expired');
+
+CREATE TABLE holds (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    account_id UUID NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    captured_amount BIGINT NOT NULL DEFAULT 0,
+    status hold_status NOT NULL DEFAULT 'pending',
+    description TEXT,
+    capture_transaction_id UUID REFERENCES transactions(id),
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (captured_amount >= 0 AND captured_amount <= amount)
+);
+
+CREATE INDEX idx_holds_user_id ON holds (user_id, created_at DESC);
+CREATE INDEX idx_holds_pending_expiry ON holds (expires_at) WHERE status = 'pending';
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TYPE user_role AS ENUM ('customer', 'support', 'admin', 'auditor');
+
+ALTER TABLE users ADD COLUMN role user_role NOT NULL DEFAULT 'customer';
+
+INSERT INTO ledger_accounts (kind, code, name) VALUES
+    ('system', 'adjustments', 'Manual adjustments');
+
+-- Audit trail for balance corrections made by staff.
+CREATE TABLE adjustments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    transaction_id UUID NOT NULL UNIQUE REFERENCES transactions(id),
+    admin_id UUID NOT NULL REFERENCES users(id),
+    reason TEXT NOT NULL CHECK (length(trim(reason)) > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX adjustments_account_id_idx ON adjustments (account_id, created_at DESC);
@@ -0,0 +1,9 @@
+-- Audit trail for reversals that only staff may make: debits, hold captures
+-- and adjustments.
+CREATE TABLE staff_reversals (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    transaction_id UUID NOT NULL UNIQUE REFERENCES transactions(id),
+    admin_id UUID NOT NULL REFERENCES users(id),
+    reason TEXT NOT NULL CHECK (length(trim(reason)) > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
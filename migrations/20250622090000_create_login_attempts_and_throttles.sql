@@ -0,0 +1,28 @@
+-- Add migration script here
+CREATE TYPE login_outcome AS ENUM ('success', 'mfa_required', 'mfa_failed', 'invalid_credentials', 'locked');
+
+-- Audit trail of every login attempt, for fraud review.
+CREATE TABLE login_attempts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email TEXT NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    ip_address TEXT NOT NULL,
+    user_agent TEXT,
+    outcome login_outcome NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_login_attempts_email ON login_attempts (email, created_at DESC);
+CREATE INDEX idx_login_attempts_ip_address ON login_attempts (ip_address, created_at DESC);
+
+-- Failure counters and lockouts, per submitted email and per client address.
+CREATE TABLE login_throttles (
+    scope TEXT NOT NULL CHECK (scope IN ('email', 'ip')),
+    key TEXT NOT NULL,
+    failed_count INT NOT NULL DEFAULT 0,
+    -- Number of lockouts so far; each one lasts twice as long as the last.
+    lockout_level INT NOT NULL DEFAULT 0,
+    locked_until TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (scope, key)
+);
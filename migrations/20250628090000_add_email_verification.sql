@@ -0,0 +1,21 @@
+-- Add migration script here
+ALTER TABLE users
+    ADD COLUMN email_verified_at TIMESTAMPTZ,
+    -- A requested new address; `email` stays in use until this one is confirmed.
+    ADD COLUMN pending_email TEXT;
+
+-- Existing users keep their ability to move money.
+UPDATE users SET email_verified_at = created_at;
+
+CREATE TABLE email_verification_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The address this token confirms.
+    email TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_email_verification_tokens_user_id ON email_verification_tokens (user_id);
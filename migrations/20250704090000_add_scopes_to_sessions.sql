@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Scopes requested at login, carried by every access token of the session.
+-- NULL grants every scope.
+ALTER TABLE sessions ADD COLUMN scopes TEXT[];
+
+-- Scopes requested with the password, applied once the second factor is given.
+ALTER TABLE mfa_challenges ADD COLUMN scopes TEXT[];
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Where a session was started and when it was last used, for listing devices.
+ALTER TABLE sessions
+    ADD COLUMN ip_address TEXT,
+    ADD COLUMN user_agent TEXT,
+    ADD COLUMN last_active_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+UPDATE sessions SET last_active_at = created_at;
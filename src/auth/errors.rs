@@ -1,6 +1,7 @@
-use actix_web::{HttpResponse, ResponseError};
 use std::fmt;
 
+/// Failures while issuing or checking credentials. Rendered to clients
+/// through [`crate::error::AppError`].
 #[derive(Debug)]
 pub enum AuthError {
     InvalidToken,
@@ -8,6 +9,7 @@ pub enum AuthError {
     TokenCreation,
     MissingToken,
     Unauthorized,
+    SessionRevoked,
 }
 
 impl fmt::Display for AuthError {
@@ -18,20 +20,7 @@ impl fmt::Display for AuthError {
             AuthError::TokenCreation => write!(f, "Token creation error"),
             AuthError::MissingToken => write!(f, "Missing authorization header"),
             AuthError::Unauthorized => write!(f, "Unauthorized"),
-        }
-    }
-}
-
-impl ResponseError for AuthError {
-    fn error_response(&self) -> HttpResponse {
-        match self {
-            AuthError::InvalidToken => HttpResponse::Unauthorized().json("Invalid token"),
-            AuthError::TokenExpired => HttpResponse::Unauthorized().json("Token expired"),
-            AuthError::TokenCreation => {
-                HttpResponse::InternalServerError().json("Token creation error")
-            }
-            AuthError::MissingToken => HttpResponse::Unauthorized().json("Missing token"),
-            AuthError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
+            AuthError::SessionRevoked => write!(f, "Session revoked"),
         }
     }
 }
@@ -1,10 +1,17 @@
-use chrono::{DateTime, Utc};
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Header, Validation, decode, decode_header, encode};
 use serde::{Deserialize, Serialize};
-use std::env;
 use uuid::Uuid;
 
 use crate::auth::errors::AuthError;
+use crate::auth::keys::{JwtKey, KeyError, KeySet};
+use crate::auth::scopes::Scope;
+use crate::models::Role;
+use crate::settings::JwtSettings;
+
+/// Clock skew tolerated when checking `exp`, in seconds.
+pub const VALIDATION_LEEWAY_SECONDS: u64 = 60;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -12,49 +19,213 @@ pub struct Claims {
     pub exp: usize,
     pub iat: usize,
     pub email: String,
+    pub sid: Uuid, // Session the token was issued for; revoking it invalidates the token
+    pub role: Role,
+    /// What the token may do. Tokens issued before scopes existed get none,
+    /// so authorization fails closed until they are refreshed.
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
 }
 
 #[derive(Debug, Clone)]
 pub struct JwtService {
-    pub secret: String,
-    pub expiration_hours: i64,
+    pub keys: KeySet,
+    pub expiration_minutes: i64,
+    pub refresh_expiration_days: i64,
 }
 
 impl JwtService {
-    pub fn from_env() -> Self {
+    pub fn new(keys: KeySet) -> Self {
         JwtService {
-            secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
-            expiration_hours: 24,
+            keys,
+            expiration_minutes: 15,
+            refresh_expiration_days: 30,
+        }
+    }
+
+    /// Signs with the RS256/EdDSA keys listed in the `keys_file` manifest,
+    /// or with HS256 and `secret` when no manifest is configured. An empty
+    /// secret is refused rather than used as a key.
+    pub fn from_settings(settings: &JwtSettings) -> Result<Self, KeyError> {
+        let keys = match (&settings.keys_file, settings.secret.as_deref()) {
+            (Some(path), _) => KeySet::from_manifest(path)?,
+            (None, Some(secret)) if !secret.is_empty() => {
+                KeySet::new(vec![JwtKey::hs256(secret.as_bytes())])
+            }
+            (None, _) => return Err(KeyError::MissingSecret),
+        };
+        if keys.signing_key(Utc::now()).is_none() {
+            tracing::warn!("No JWT signing key is active yet; logins will fail until one is");
         }
+
+        Ok(JwtService {
+            keys,
+            expiration_minutes: settings.access_token_minutes,
+            refresh_expiration_days: settings.refresh_token_days,
+        })
     }
 
-    pub fn generate_token(&self, user_id: Uuid, email: &str) -> Result<String, AuthError> {
+    /// Lifetime of an access token, in seconds.
+    pub fn expires_in(&self) -> i64 {
+        self.expiration_minutes * 60
+    }
+
+    /// How long a superseded key keeps verifying: as long as the last token
+    /// it signed can still be accepted.
+    pub fn key_grace_period(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.expiration_minutes)
+            + chrono::Duration::seconds(VALIDATION_LEEWAY_SECONDS as i64)
+    }
+
+    /// Public keys for verifiers, as served at `/.well-known/jwks.json`.
+    pub fn jwks(&self) -> JwkSet {
+        self.keys.jwks(Utc::now(), self.key_grace_period())
+    }
+
+    /// Lifetime of a refresh token.
+    pub fn refresh_ttl(&self) -> chrono::Duration {
+        chrono::Duration::days(self.refresh_expiration_days)
+    }
+
+    pub fn generate_token(
+        &self,
+        user_id: Uuid,
+        email: &str,
+        role: Role,
+        session_id: Uuid,
+        scopes: &[Scope],
+    ) -> Result<String, AuthError> {
         let now = Utc::now().timestamp() as usize;
         let expiration =
-            (Utc::now() + chrono::Duration::hours(self.expiration_hours)).timestamp() as usize;
+            (Utc::now() + chrono::Duration::minutes(self.expiration_minutes)).timestamp() as usize;
 
         let claims = Claims {
             sub: user_id,
             exp: expiration,
             iat: now,
             email: email.to_owned(),
+            sid: session_id,
+            role,
+            scopes: scopes.to_vec(),
         };
 
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.secret.as_ref()),
-        )
-        .map_err(|_| AuthError::TokenCreation)
+        let key = self
+            .keys
+            .signing_key(Utc::now())
+            .ok_or(AuthError::TokenCreation)?;
+        let header = Header {
+            kid: key.kid.clone(),
+            ..Header::new(key.algorithm)
+        };
+
+        encode(&header, &claims, key.encoding()).map_err(|_| AuthError::TokenCreation)
     }
 
+    /// Checks the token against the key named by its `kid`. The key also fixes
+    /// the algorithm, so a token cannot choose a weaker one.
     pub fn validate_token(&self, token: &str) -> Result<Claims, AuthError> {
-        decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.secret.as_ref()),
-            &Validation::new(Algorithm::HS256),
+        let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+        let key = self
+            .keys
+            .verification_key(header.kid.as_deref(), Utc::now(), self.key_grace_period())
+            .ok_or(AuthError::InvalidToken)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.leeway = VALIDATION_LEEWAY_SECONDS;
+
+        decode::<Claims>(token, key.decoding(), &validation)
+            .map_err(|_| AuthError::InvalidToken)
+            .map(|data| data.claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::Algorithm;
+
+    fn rs256_key(kid: &str) -> JwtKey {
+        JwtKey::asymmetric(
+            kid,
+            Algorithm::RS256,
+            include_bytes!("testdata/rs256.pem"),
+            include_str!("testdata/rs256.pub.pem"),
+            Utc::now() - chrono::Duration::minutes(1),
+        )
+        .unwrap()
+    }
+
+    fn issue(jwt: &JwtService) -> String {
+        jwt.generate_token(
+            Uuid::new_v4(),
+            "alice@example.com",
+            Role::Customer,
+            Uuid::new_v4(),
+            &[Scope::BalanceRead],
         )
-        .map_err(|_| AuthError::InvalidToken)
-        .map(|data| data.claims)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hs256_round_trip() {
+        let jwt = JwtService::new(KeySet::new(vec![JwtKey::hs256(b"secret")]));
+        let token = issue(&jwt);
+
+        assert_eq!(decode_header(&token).unwrap().kid, None);
+        let claims = jwt.validate_token(&token).unwrap();
+        assert_eq!(claims.email, "alice@example.com");
+        assert_eq!(claims.scopes, vec![Scope::BalanceRead]);
+        assert!(jwt.jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn test_rs256_tokens_name_their_key() {
+        let jwt = JwtService::new(KeySet::new(vec![rs256_key("2025-06")]));
+        let token = issue(&jwt);
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        assert_eq!(header.kid.as_deref(), Some("2025-06"));
+        assert!(jwt.validate_token(&token).is_ok());
+
+        let other = JwtService::new(KeySet::new(vec![rs256_key("2025-07")]));
+        assert!(other.validate_token(&token).is_err());
+    }
+
+    #[test]
+    fn test_shared_secret_tokens_are_refused_by_asymmetric_keys() {
+        let hs256 = JwtService::new(KeySet::new(vec![JwtKey::hs256(b"secret")]));
+        let rs256 = JwtService::new(KeySet::new(vec![rs256_key("2025-06")]));
+        assert!(rs256.validate_token(&issue(&hs256)).is_err());
+    }
+
+    #[test]
+    fn test_settings_without_a_secret_are_refused() {
+        let mut settings = JwtSettings::default();
+        assert!(matches!(JwtService::from_settings(&settings), Err(KeyError::MissingSecret)));
+
+        settings.secret = Some(String::new());
+        assert!(matches!(JwtService::from_settings(&settings), Err(KeyError::MissingSecret)));
+
+        settings.secret = Some("secret".into());
+        let jwt = JwtService::from_settings(&settings).unwrap();
+        assert!(jwt.validate_token(&issue(&jwt)).is_ok());
+    }
+
+    #[test]
+    fn test_tokens_without_scopes_get_none() {
+        let jwt = JwtService::new(KeySet::new(vec![JwtKey::hs256(b"secret")]));
+        let legacy = serde_json::json!({
+            "sub": Uuid::new_v4(),
+            "exp": (Utc::now() + chrono::Duration::minutes(5)).timestamp(),
+            "iat": Utc::now().timestamp(),
+            "email": "alice@example.com",
+            "sid": Uuid::new_v4(),
+            "role": "customer",
+        });
+        let key = jwt.keys.signing_key(Utc::now()).unwrap();
+        let token = encode(&Header::default(), &legacy, key.encoding()).unwrap();
+
+        assert!(jwt.validate_token(&token).unwrap().scopes.is_empty());
     }
 }
@@ -0,0 +1,388 @@
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use pkcs1::RsaPublicKey;
+use serde::Deserialize;
+use spki::der::{Decode, pem};
+use spki::{ObjectIdentifier, SubjectPublicKeyInfoOwned};
+use std::path::{Path, PathBuf};
+use std::{fmt, fs};
+use thiserror::Error;
+
+// Algorithm identifiers of the public keys we accept.
+const RSA_ENCRYPTION_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("invalid key manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+
+    #[error("key {kid}: {reason}")]
+    InvalidKey { kid: String, reason: String },
+
+    #[error("jwt.secret must be set when jwt.keys_file is not")]
+    MissingSecret,
+}
+
+/// One key tokens are signed and verified with.
+#[derive(Clone)]
+pub struct JwtKey {
+    /// Sent in the token header so verifiers can pick the right key. The
+    /// HS256 development key has none.
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    /// When the key starts signing new tokens.
+    pub active_from: DateTime<Utc>,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// The public half, published in the JWKS. Only asymmetric keys have one.
+    jwk: Option<Jwk>,
+}
+
+impl fmt::Debug for JwtKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JwtKey")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .field("active_from", &self.active_from)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtKey {
+    /// A shared-secret key for local development. It never appears in the JWKS.
+    pub fn hs256(secret: &[u8]) -> Self {
+        JwtKey {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            active_from: DateTime::<Utc>::MIN_UTC,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    /// An RS256 or EdDSA key from a PEM private key and its PEM
+    /// (SubjectPublicKeyInfo) public key.
+    pub fn asymmetric(
+        kid: &str,
+        algorithm: Algorithm,
+        private_pem: &[u8],
+        public_pem: &str,
+        active_from: DateTime<Utc>,
+    ) -> Result<Self, KeyError> {
+        let invalid = |reason: String| KeyError::InvalidKey {
+            kid: kid.to_owned(),
+            reason,
+        };
+
+        let encoding = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(private_pem),
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem),
+            other => return Err(invalid(format!("unsupported algorithm {:?}", other))),
+        }
+        .map_err(|e| invalid(e.to_string()))?;
+
+        let key_material = public_key_parameters(algorithm, public_pem).map_err(invalid)?;
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                algorithm: Some(algorithm),
+                key_id: Some(kid.to_owned()),
+                ..Default::default()
+            },
+            algorithm: key_material,
+        };
+        let decoding = DecodingKey::from_jwk(&jwk).map_err(|e| invalid(e.to_string()))?;
+
+        Ok(JwtKey {
+            kid: Some(kid.to_owned()),
+            algorithm,
+            active_from,
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn encoding(&self) -> &EncodingKey {
+        &self.encoding
+    }
+
+    pub fn decoding(&self) -> &DecodingKey {
+        &self.decoding
+    }
+}
+
+/// The keys in rotation, ordered by activation time.
+///
+/// The newest active key signs. A key that has been superseded still
+/// verifies for `grace` (the access token lifetime), so tokens it signed
+/// stay valid until they expire; after that it is retired. Keys scheduled
+/// for the future are published early so verifiers can cache them.
+#[derive(Debug, Clone)]
+pub struct KeySet {
+    keys: Vec<JwtKey>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    keys: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    kid: String,
+    algorithm: Algorithm,
+    private_key: PathBuf,
+    public_key: PathBuf,
+    active_from: DateTime<Utc>,
+}
+
+impl KeySet {
+    pub fn new(mut keys: Vec<JwtKey>) -> Self {
+        keys.sort_by_key(|key| key.active_from);
+        KeySet { keys }
+    }
+
+    /// Loads keys from a JSON manifest. Key paths are relative to the
+    /// manifest's directory.
+    pub fn from_manifest(path: &Path) -> Result<Self, KeyError> {
+        let manifest: Manifest = serde_json::from_str(&read(path)?)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let keys = manifest
+            .keys
+            .iter()
+            .map(|entry| {
+                JwtKey::asymmetric(
+                    &entry.kid,
+                    entry.algorithm,
+                    read(&base.join(&entry.private_key))?.as_bytes(),
+                    &read(&base.join(&entry.public_key))?,
+                    entry.active_from,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(KeySet::new(keys))
+    }
+
+    /// The key new tokens are signed with at `now`.
+    pub fn signing_key(&self, now: DateTime<Utc>) -> Option<&JwtKey> {
+        self.keys.iter().rev().find(|key| key.active_from <= now)
+    }
+
+    /// The key a token with this `kid` header is checked against, unless
+    /// it is unknown or retired.
+    pub fn verification_key(
+        &self,
+        kid: Option<&str>,
+        now: DateTime<Utc>,
+        grace: Duration,
+    ) -> Option<&JwtKey> {
+        self.live(now, grace).find(|key| key.kid.as_deref() == kid)
+    }
+
+    /// The public keys verifiers should trust at `now`.
+    pub fn jwks(&self, now: DateTime<Utc>, grace: Duration) -> JwkSet {
+        JwkSet {
+            keys: self.live(now, grace).filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+
+    /// Keys that are not yet retired.
+    fn live(&self, now: DateTime<Utc>, grace: Duration) -> impl Iterator<Item = &JwtKey> {
+        self.keys.iter().enumerate().filter_map(move |(i, key)| {
+            let retired = self
+                .keys
+                .get(i + 1)
+                .is_some_and(|next| next.active_from + grace <= now);
+            (!retired).then_some(key)
+        })
+    }
+}
+
+fn read(path: &Path) -> Result<String, KeyError> {
+    fs::read_to_string(path).map_err(|source| KeyError::Io {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Extracts the JWK parameters from a PEM SubjectPublicKeyInfo.
+fn public_key_parameters(algorithm: Algorithm, pem: &str) -> Result<AlgorithmParameters, String> {
+    // Decoded in two steps: `DecodePem` can spin forever on a truncated body.
+    let (label, der) =
+        pem::decode_vec(pem.as_bytes()).map_err(|_| "public key is not valid PEM".to_owned())?;
+    if label != "PUBLIC KEY" {
+        return Err(format!("expected a PUBLIC KEY, found {}", label));
+    }
+    let spki = SubjectPublicKeyInfoOwned::from_der(&der)
+        .map_err(|e| format!("public key is not a valid SubjectPublicKeyInfo: {}", e))?;
+    let key = spki
+        .subject_public_key
+        .as_bytes()
+        .ok_or_else(|| "public key is not a whole number of bytes".to_owned())?;
+
+    match algorithm {
+        Algorithm::RS256 if spki.algorithm.oid == RSA_ENCRYPTION_OID => {
+            let rsa_key = RsaPublicKey::try_from(key)
+                .map_err(|e| format!("public key is not a valid RSA key: {}", e))?;
+            Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: BASE64URL_NOPAD.encode(rsa_key.modulus.as_bytes()),
+                e: BASE64URL_NOPAD.encode(rsa_key.public_exponent.as_bytes()),
+            }))
+        }
+        Algorithm::EdDSA if spki.algorithm.oid == ED25519_OID && key.len() == 32 => {
+            Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: BASE64URL_NOPAD.encode(key),
+            }))
+        }
+        _ => Err(format!("public key does not match algorithm {:?}", algorithm)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::BASE64;
+
+    const RSA_PRIVATE: &str = include_str!("testdata/rs256.pem");
+    const RSA_PUBLIC: &str = include_str!("testdata/rs256.pub.pem");
+    const ED_PRIVATE: &str = include_str!("testdata/ed25519.pem");
+    const ED_PUBLIC: &str = include_str!("testdata/ed25519.pub.pem");
+
+    fn key(kid: &str, algorithm: Algorithm, active_from: DateTime<Utc>) -> JwtKey {
+        let (private, public) = match algorithm {
+            Algorithm::RS256 => (RSA_PRIVATE, RSA_PUBLIC),
+            _ => (ED_PRIVATE, ED_PUBLIC),
+        };
+        JwtKey::asymmetric(kid, algorithm, private.as_bytes(), public, active_from).unwrap()
+    }
+
+    #[test]
+    fn test_public_keys_become_jwks() {
+        let now = Utc::now();
+        let keys = KeySet::new(vec![
+            key("rsa", Algorithm::RS256, now),
+            key("ed", Algorithm::EdDSA, now),
+        ]);
+        let jwks = serde_json::to_value(keys.jwks(now, Duration::minutes(15))).unwrap();
+
+        let rsa = &jwks["keys"][0];
+        assert_eq!(rsa["kty"], "RSA");
+        assert_eq!(rsa["kid"], "rsa");
+        assert_eq!(rsa["alg"], "RS256");
+        assert_eq!(rsa["use"], "sig");
+        assert_eq!(rsa["e"], "AQAB");
+        assert_eq!(BASE64URL_NOPAD.decode(rsa["n"].as_str().unwrap().as_bytes()).unwrap().len(), 256);
+
+        let ed = &jwks["keys"][1];
+        assert_eq!(ed["kty"], "OKP");
+        assert_eq!(ed["crv"], "Ed25519");
+        assert_eq!(ed["alg"], "EdDSA");
+    }
+
+    #[test]
+    fn test_mismatched_key_is_rejected() {
+        let result = JwtKey::asymmetric(
+            "bad",
+            Algorithm::RS256,
+            RSA_PRIVATE.as_bytes(),
+            ED_PUBLIC,
+            Utc::now(),
+        );
+        assert!(matches!(result, Err(KeyError::InvalidKey { .. })));
+    }
+
+    fn public_der(pem: &str) -> Vec<u8> {
+        let body: String = pem.lines().filter(|line| !line.starts_with("-----")).collect();
+        BASE64.decode(body.as_bytes()).unwrap()
+    }
+
+    fn public_pem(der: &[u8]) -> String {
+        let body = BASE64.encode(der);
+        let lines: Vec<&str> = body
+            .as_bytes()
+            .chunks(64)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", lines.join("\n"))
+    }
+
+    #[test]
+    fn test_keys_for_the_other_algorithm_are_rejected() {
+        assert!(public_key_parameters(Algorithm::RS256, ED_PUBLIC).is_err());
+        assert!(public_key_parameters(Algorithm::EdDSA, RSA_PUBLIC).is_err());
+        assert!(public_key_parameters(Algorithm::ES256, RSA_PUBLIC).is_err());
+    }
+
+    #[test]
+    fn test_malformed_public_keys_are_rejected() {
+        let der = public_der(RSA_PUBLIC);
+        assert!(public_key_parameters(Algorithm::RS256, &public_pem(&der)).is_ok());
+
+        // Truncated anywhere, including inside the modulus.
+        for len in [0, 1, 4, 20, der.len() / 2, der.len() - 1] {
+            let pem = public_pem(&der[..len]);
+            assert!(public_key_parameters(Algorithm::RS256, &pem).is_err(), "truncated to {}", len);
+        }
+
+        // An outer length longer than the input, or one that needs more
+        // octets than any real key.
+        let mut oversized = der.clone();
+        oversized[2..4].copy_from_slice(&[0xff, 0xff]);
+        assert!(public_key_parameters(Algorithm::RS256, &public_pem(&oversized)).is_err());
+        let mut too_many_octets = der.clone();
+        too_many_octets[1] = 0x89;
+        assert!(public_key_parameters(Algorithm::RS256, &public_pem(&too_many_octets)).is_err());
+
+        // Trailing bytes after the key.
+        let mut trailing = der.clone();
+        trailing.push(0);
+        assert!(public_key_parameters(Algorithm::RS256, &public_pem(&trailing)).is_err());
+
+        assert!(public_key_parameters(Algorithm::RS256, "not a key").is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_keys_until_their_tokens_expire() {
+        let now = Utc::now();
+        let grace = Duration::minutes(15);
+        let keys = KeySet::new(vec![
+            key("next", Algorithm::EdDSA, now + Duration::days(1)),
+            key("old", Algorithm::RS256, now - Duration::days(30)),
+            key("previous", Algorithm::EdDSA, now - Duration::days(1)),
+            key("current", Algorithm::RS256, now - Duration::minutes(5)),
+        ]);
+
+        assert_eq!(keys.signing_key(now).unwrap().kid.as_deref(), Some("current"));
+        assert!(keys.verification_key(Some("previous"), now, grace).is_some());
+        assert!(keys.verification_key(Some("previous"), now + grace, grace).is_none());
+        assert!(keys.verification_key(Some("old"), now, grace).is_none());
+        assert!(keys.verification_key(None, now, grace).is_none());
+
+        let published: Vec<_> = keys
+            .jwks(now, grace)
+            .keys
+            .into_iter()
+            .filter_map(|jwk| jwk.common.key_id)
+            .collect();
+        assert_eq!(published, vec!["previous", "current", "next"]);
+    }
+}
@@ -0,0 +1,69 @@
+use chrono::Duration;
+use serde::Deserialize;
+use validator::Validate;
+
+/// Thresholds for locking out repeated failed logins: the `[login]` section
+/// of the settings. The `LOGIN_*` variables named below still override it.
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct LoginPolicy {
+    /// Failed attempts for one email before it is locked (`LOGIN_MAX_FAILURES_PER_EMAIL`).
+    #[validate(range(min = 1))]
+    pub max_failures_per_email: i32,
+    /// Failed attempts from one address before it is locked (`LOGIN_MAX_FAILURES_PER_IP`).
+    #[validate(range(min = 1))]
+    pub max_failures_per_ip: i32,
+    /// Length of the first lockout; each further lockout doubles it
+    /// (`LOGIN_LOCKOUT_SECONDS`).
+    #[validate(range(min = 1))]
+    pub base_lockout_seconds: i64,
+    /// Upper bound on a single lockout (`LOGIN_MAX_LOCKOUT_SECONDS`).
+    #[validate(range(min = 1))]
+    pub max_lockout_seconds: i64,
+    /// Counters untouched for this long start over (`LOGIN_FAILURE_WINDOW_SECONDS`).
+    #[validate(range(min = 1))]
+    pub failure_window_seconds: i64,
+}
+
+impl Default for LoginPolicy {
+    fn default() -> Self {
+        LoginPolicy {
+            max_failures_per_email: 5,
+            max_failures_per_ip: 20,
+            base_lockout_seconds: 60,
+            max_lockout_seconds: 24 * 60 * 60,
+            failure_window_seconds: 24 * 60 * 60,
+        }
+    }
+}
+
+impl LoginPolicy {
+    /// How long the lockout after `previous_lockouts` earlier ones lasts.
+    pub fn lockout_duration(&self, previous_lockouts: i32) -> Duration {
+        let factor = 1i64.checked_shl(previous_lockouts.clamp(0, 32) as u32).unwrap_or(i64::MAX);
+        Duration::seconds(
+            self.base_lockout_seconds
+                .saturating_mul(factor)
+                .min(self.max_lockout_seconds),
+        )
+    }
+
+    pub fn failure_window(&self) -> Duration {
+        Duration::seconds(self.failure_window_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockouts_escalate_up_to_the_cap() {
+        let policy = LoginPolicy::default();
+        assert_eq!(policy.lockout_duration(0), Duration::seconds(60));
+        assert_eq!(policy.lockout_duration(1), Duration::seconds(120));
+        assert_eq!(policy.lockout_duration(3), Duration::seconds(480));
+        assert_eq!(policy.lockout_duration(20), Duration::seconds(24 * 60 * 60));
+        assert_eq!(policy.lockout_duration(1000), Duration::seconds(24 * 60 * 60));
+    }
+}
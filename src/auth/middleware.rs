@@ -1,36 +1,146 @@
 use actix_web::{
     Error as ActixError, FromRequest, HttpMessage, HttpRequest,
-    dev::{Payload, ServiceRequest},
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    middleware::Next,
     web,
 };
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use futures::future::{Ready, ready};
+use std::marker::PhantomData;
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::auth::jwt::{Claims, JwtService};
+use crate::auth::errors::AuthError;
+use crate::auth::jwt::JwtService;
+use crate::auth::scopes::Scope;
+use crate::error::AppError;
+use crate::models::api_key::API_KEY_PREFIX;
+use crate::models::{ApiKey, Role, Session, User};
+use crate::telemetry;
 
 pub async fn jwt_validator(
     req: ServiceRequest,
-    credentials: BearerAuth,
+    credentials: Option<BearerAuth>,
 ) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
-    //let jwt_service = req.app_data::<JwtService>().expect("JwtService not found in app data");
-    let jwt_service = req
-        .app_data::<web::Data<JwtService>>()
-        .expect("JwtService not found in app data");
+    let Some(credentials) = credentials else {
+        return Err((AppError::from(AuthError::MissingToken).into(), req));
+    };
+
+    let token = credentials.token();
+    let user = if token.starts_with(API_KEY_PREFIX) {
+        authenticate_api_key(token, &req).await
+    } else {
+        authenticate_jwt(token, &req).await
+    };
 
-    match jwt_service.validate_token(credentials.token()) {
-        Ok(claims) => {
-            req.extensions_mut().insert(claims);
+    match user {
+        Ok(user) => {
+            telemetry::record_user(user.user_id);
+            req.extensions_mut().insert(user);
             Ok(req)
         }
         Err(e) => Err((e.into(), req)),
     }
 }
 
-#[derive(Debug)]
+async fn authenticate_jwt(token: &str, req: &ServiceRequest) -> Result<AuthenticatedUser, AppError> {
+    let jwt_service = req
+        .app_data::<web::Data<JwtService>>()
+        .expect("JwtService not found in app data");
+    let claims = jwt_service.validate_token(token)?;
+
+    if !Session::record_activity(claims.sid, pool(req)).await? {
+        return Err(AuthError::SessionRevoked.into());
+    }
+
+    Ok(AuthenticatedUser {
+        user_id: claims.sub,
+        email: claims.email,
+        role: claims.role,
+        credential: Credential::Session(claims.sid),
+        scopes: claims.scopes,
+    })
+}
+
+/// API keys resolve to their owner with the owner's current role, limited
+/// to the key's scopes.
+async fn authenticate_api_key(
+    secret: &str,
+    req: &ServiceRequest,
+) -> Result<AuthenticatedUser, AppError> {
+    let pool = pool(req);
+    let key = ApiKey::authenticate(secret, pool)
+        .await?
+        .ok_or(AuthError::InvalidToken)?;
+    let user = User::get_by_id(&key.user_id, pool).await?;
+
+    Ok(AuthenticatedUser {
+        user_id: user.id,
+        email: user.email,
+        role: user.role,
+        credential: Credential::ApiKey(key.id),
+        scopes: key.scopes,
+    })
+}
+
+fn pool(req: &ServiceRequest) -> &PgPool {
+    req.app_data::<web::Data<PgPool>>()
+        .expect("PgPool not found in app data")
+}
+
+/// Refuses the request with 403 unless its credential carries `scope`.
+/// Attach to protected routes with `middleware::from_fn`.
+pub async fn require_scope<B: MessageBody + 'static>(
+    scope: Scope,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<impl MessageBody>, ActixError> {
+    let allowed = req
+        .extensions()
+        .get::<AuthenticatedUser>()
+        .is_some_and(|user| user.has_scope(scope));
+    if !allowed {
+        // Rendered here, while the request id is still in scope.
+        let err = AppError::Forbidden(format!("This credential lacks the {} scope", scope));
+        return Ok(req.error_response(err).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// How a request authenticated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Credential {
+    /// An access token issued for this login session.
+    Session(Uuid),
+    ApiKey(Uuid),
+}
+
+#[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
     pub user_id: Uuid,
     pub email: String,
+    pub role: Role,
+    pub credential: Credential,
+    pub scopes: Vec<Scope>,
+}
+
+impl AuthenticatedUser {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// The login session behind the request. Requests made with an API key
+    /// have none.
+    pub fn session_id(&self) -> Result<Uuid, AppError> {
+        match self.credential {
+            Credential::Session(id) => Ok(id),
+            Credential::ApiKey(_) => Err(AppError::Forbidden(
+                "This operation requires a login session, not an API key".into(),
+            )),
+        }
+    }
 }
 
 impl FromRequest for AuthenticatedUser {
@@ -38,14 +148,69 @@ impl FromRequest for AuthenticatedUser {
     type Future = Ready<Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let extensions = req.extensions();
-        let claims = extensions
-            .get::<Claims>()
-            .expect("Claims not found in request extensions");
-
-        ready(Ok(AuthenticatedUser {
-            user_id: claims.sub,
-            email: claims.email.clone(),
+        let user = req
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .cloned()
+            .expect("AuthenticatedUser not found in request extensions");
+
+        ready(Ok(user))
+    }
+}
+
+/// A set of roles a route accepts. Implemented by the marker types used
+/// with [`AuthorizedUser`].
+pub trait RoleRequirement {
+    fn allows(role: Role) -> bool;
+}
+
+/// Any back-office role: support, admin or auditor.
+#[derive(Debug)]
+pub struct Staff;
+
+impl RoleRequirement for Staff {
+    fn allows(role: Role) -> bool {
+        role.is_staff()
+    }
+}
+
+/// Admins only.
+#[derive(Debug)]
+pub struct Admin;
+
+impl RoleRequirement for Admin {
+    fn allows(role: Role) -> bool {
+        role == Role::Admin
+    }
+}
+
+/// An authenticated user whose role satisfies `R`; anyone else is
+/// rejected with 403 before the handler runs.
+#[derive(Debug)]
+pub struct AuthorizedUser<R: RoleRequirement> {
+    pub user: AuthenticatedUser,
+    _requirement: PhantomData<R>,
+}
+
+impl<R: RoleRequirement> FromRequest for AuthorizedUser<R> {
+    type Error = ActixError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = match AuthenticatedUser::from_request(req, payload).into_inner() {
+            Ok(user) => user,
+            Err(e) => return ready(Err(e)),
+        };
+        if !R::allows(user.role) {
+            return ready(Err(AppError::Forbidden(
+                "Your role does not permit this operation".into(),
+            )
+            .into()));
+        }
+
+        ready(Ok(AuthorizedUser {
+            user,
+            _requirement: PhantomData,
         }))
     }
 }
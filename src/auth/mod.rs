@@ -1,7 +1,16 @@
 pub mod errors;
 pub mod jwt;
+pub mod keys;
+pub mod lockout;
 pub mod middleware;
+pub mod scopes;
+pub mod tokens;
+pub mod totp;
 
 pub use errors::AuthError;
 pub use jwt::JwtService;
-pub use middleware::{AuthenticatedUser, jwt_validator};
+pub use middleware::{
+    Admin, AuthenticatedUser, AuthorizedUser, Credential, RoleRequirement, Staff, jwt_validator,
+    require_scope,
+};
+pub use scopes::Scope;
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// A permission a credential may carry. Routes name the scope they need;
+/// requests whose credential lacks it are refused with 403.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "text")]
+pub enum Scope {
+    #[serde(rename = "profile:read")]
+    #[sqlx(rename = "profile:read")]
+    ProfileRead,
+    #[serde(rename = "profile:write")]
+    #[sqlx(rename = "profile:write")]
+    ProfileWrite,
+    #[serde(rename = "accounts:read")]
+    #[sqlx(rename = "accounts:read")]
+    AccountsRead,
+    #[serde(rename = "accounts:write")]
+    #[sqlx(rename = "accounts:write")]
+    AccountsWrite,
+    #[serde(rename = "balance:read")]
+    #[sqlx(rename = "balance:read")]
+    BalanceRead,
+    #[serde(rename = "transactions:read")]
+    #[sqlx(rename = "transactions:read")]
+    TransactionsRead,
+    /// Transactions, transfers, reversals and holds.
+    #[serde(rename = "transactions:write")]
+    #[sqlx(rename = "transactions:write")]
+    TransactionsWrite,
+    /// Back-office reads; the user's role must allow them too.
+    #[serde(rename = "admin:read")]
+    #[sqlx(rename = "admin:read")]
+    AdminRead,
+    #[serde(rename = "admin:write")]
+    #[sqlx(rename = "admin:write")]
+    AdminWrite,
+    /// Managing how the user signs in: passwords, 2FA, email and API keys.
+    #[serde(rename = "credentials:write")]
+    #[sqlx(rename = "credentials:write")]
+    CredentialsWrite,
+}
+
+impl Scope {
+    pub const ALL: &[Scope] = &[
+        Scope::ProfileRead,
+        Scope::ProfileWrite,
+        Scope::AccountsRead,
+        Scope::AccountsWrite,
+        Scope::BalanceRead,
+        Scope::TransactionsRead,
+        Scope::TransactionsWrite,
+        Scope::AdminRead,
+        Scope::AdminWrite,
+        Scope::CredentialsWrite,
+    ];
+
+    /// Every scope, for credentials with full access.
+    pub fn all() -> Vec<Scope> {
+        Scope::ALL.to_vec()
+    }
+
+    /// `scopes` sorted in a stable order and without duplicates.
+    pub fn normalize(scopes: &[Scope]) -> Vec<Scope> {
+        let mut scopes = scopes.to_vec();
+        scopes.sort_by_key(|s| s.as_str());
+        scopes.dedup();
+        scopes
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "profile:read",
+            Scope::ProfileWrite => "profile:write",
+            Scope::AccountsRead => "accounts:read",
+            Scope::AccountsWrite => "accounts:write",
+            Scope::BalanceRead => "balance:read",
+            Scope::TransactionsRead => "transactions:read",
+            Scope::TransactionsWrite => "transactions:write",
+            Scope::AdminRead => "admin:read",
+            Scope::AdminWrite => "admin:write",
+            Scope::CredentialsWrite => "credentials:write",
+        }
+    }
+
+    /// Whether an API key may carry this scope. Keys cannot manage
+    /// credentials, so a leaked key cannot take over the account.
+    pub fn grantable_to_api_keys(&self) -> bool {
+        *self != Scope::CredentialsWrite
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_names_round_trip() {
+        for scope in Scope::ALL {
+            let json = serde_json::to_string(scope).unwrap();
+            assert_eq!(json, format!("\"{}\"", scope.as_str()));
+            assert_eq!(serde_json::from_str::<Scope>(&json).unwrap(), *scope);
+        }
+        assert!(serde_json::from_str::<Scope>("\"everything\"").is_err());
+    }
+
+    #[test]
+    fn test_normalize_sorts_and_dedups() {
+        let scopes = [Scope::TransactionsRead, Scope::BalanceRead, Scope::TransactionsRead];
+        assert_eq!(
+            Scope::normalize(&scopes),
+            vec![Scope::BalanceRead, Scope::TransactionsRead]
+        );
+    }
+}
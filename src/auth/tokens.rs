@@ -0,0 +1,39 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Number of random bytes in an opaque token (hex encoded, so twice as many characters).
+pub const TOKEN_BYTES: usize = 32;
+
+/// Generates a random opaque token suitable for handing to a client once.
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Opaque tokens are stored as SHA-256 digests so a database leak does not
+/// reveal usable credentials.
+pub fn hash_opaque_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_tokens_are_unique_hex() {
+        let a = generate_opaque_token();
+        let b = generate_opaque_token();
+        assert_ne!(a, b);
+        assert_eq!(a.len(), TOKEN_BYTES * 2);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_hash_is_stable_and_differs_from_token() {
+        let token = generate_opaque_token();
+        assert_eq!(hash_opaque_token(&token), hash_opaque_token(&token));
+        assert_ne!(hash_opaque_token(&token), token);
+    }
+}
@@ -0,0 +1,132 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Issuer shown by authenticator apps next to the account name.
+pub const ISSUER: &str = "Banking API";
+/// Length of a generated secret (160 bits, as recommended by RFC 4226).
+pub const SECRET_BYTES: usize = 20;
+pub const DIGITS: u32 = 6;
+pub const PERIOD_SECONDS: i64 = 30;
+/// Codes from this many steps either side of now are accepted, to tolerate
+/// clock drift on the user's device.
+pub const ALLOWED_SKEW_STEPS: i64 = 1;
+
+/// Generates a new base32-encoded TOTP secret.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+pub fn time_step(unix_seconds: i64) -> i64 {
+    unix_seconds.div_euclid(PERIOD_SECONDS)
+}
+
+/// The RFC 4226 HOTP value for `counter`, zero-padded to [`DIGITS`].
+pub fn code_at(secret: &[u8], counter: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Checks `code` against the secret around `now`. Returns the matching time
+/// step so callers can reject replays of the same code.
+pub fn verify(secret: &str, code: &str, unix_seconds: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let now = time_step(unix_seconds);
+
+    (now - ALLOWED_SKEW_STEPS..=now + ALLOWED_SKEW_STEPS)
+        .find(|&step| constant_time_eq(code_at(&secret, step).as_bytes(), code.as_bytes()))
+}
+
+/// The `otpauth://` URI authenticator apps import, usually via a QR code.
+pub fn otpauth_uri(secret: &str, account_name: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(ISSUER),
+        percent_encode(account_name),
+        secret,
+        percent_encode(ISSUER),
+        DIGITS,
+        PERIOD_SECONDS
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B, SHA-1 key.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc_6238_vectors() {
+        // The RFC lists 8-digit codes; the last six digits are the 6-digit code.
+        assert_eq!(code_at(RFC_SECRET, time_step(59)), "287082");
+        assert_eq!(code_at(RFC_SECRET, time_step(1111111109)), "081804");
+        assert_eq!(code_at(RFC_SECRET, time_step(1234567890)), "005924");
+    }
+
+    #[test]
+    fn test_verify_accepts_adjacent_steps_only() {
+        let secret = BASE32_NOPAD.encode(RFC_SECRET);
+        let code = code_at(RFC_SECRET, time_step(1111111109));
+
+        assert_eq!(verify(&secret, &code, 1111111109), Some(time_step(1111111109)));
+        assert!(verify(&secret, &code, 1111111109 + PERIOD_SECONDS).is_some());
+        assert!(verify(&secret, &code, 1111111109 + 3 * PERIOD_SECONDS).is_none());
+        assert!(verify(&secret, "12345", 1111111109).is_none());
+        assert!(verify(&secret, "abcdef", 1111111109).is_none());
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri("JBSWY3DPEHPK3PXP", "alice@example.com");
+        assert_eq!(
+            uri,
+            "otpauth://totp/Banking%20API:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Banking%20API&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn test_generated_secret_decodes() {
+        let secret = generate_secret();
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), SECRET_BYTES);
+    }
+}
@@ -1,4 +1,14 @@
+use actix_web::http::{StatusCode, header};
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::auth::AuthError;
+use crate::request_id;
+
+/// Content type of every error body, per RFC 7807.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -10,15 +20,284 @@ pub enum AppError {
 
     #[error("Validation error: {0}")]
     ValidationError(String),
+
+    /// A request payload broke one or more field rules.
+    #[error("Invalid fields: {0:?}")]
+    InvalidFields(Vec<FieldError>),
+
+    #[error("{0} not found")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// Too many attempts; the client may retry after this many seconds.
+    #[error("Too many attempts; try again in {0} seconds")]
+    RateLimited(i64),
+
+    #[error("Internal error: {0}")]
+    Internal(String),
     // Add other error variants as needed
 }
 
-impl actix_web::error::ResponseError for AppError {
-    fn status_code(&self) -> actix_web::http::StatusCode {
+/// One rule a request field broke.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Path to the field, e.g. `amount` or `items[0].name`.
+    pub field: String,
+    /// Stable, machine-readable rule name, e.g. `length` or `range`.
+    pub code: String,
+    pub message: String,
+}
+
+/// An RFC 7807 problem details body.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Stable, machine-readable error code.
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Field-level failures, for validation errors.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl AppError {
+    /// Stable, machine-readable error code. Clients should match on this
+    /// rather than on the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::DatabaseError(_) | AppError::Internal(_) => "internal_error",
+            AppError::InsufficientFunds => "insufficient_funds",
+            AppError::ValidationError(_) | AppError::InvalidFields(_) => "validation_error",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::RateLimited(_) => "rate_limited",
+        }
+    }
+
+    /// Human-readable message safe to show to clients. Internal failures
+    /// are never described beyond a generic message.
+    pub fn detail(&self) -> String {
         match self {
-            AppError::DatabaseError(_) => actix_web::http::StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::InsufficientFunds => actix_web::http::StatusCode::BAD_REQUEST,
-            AppError::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            AppError::DatabaseError(_) | AppError::Internal(_) => {
+                "An internal error occurred".to_owned()
+            }
+            AppError::ValidationError(message)
+            | AppError::Conflict(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message) => message.clone(),
+            AppError::InvalidFields(_) => "One or more fields are invalid".to_owned(),
+            AppError::InsufficientFunds | AppError::NotFound(_) | AppError::RateLimited(_) => {
+                self.to_string()
+            }
         }
     }
+
+    pub fn problem(&self) -> Problem {
+        let status = self.status_code();
+        Problem {
+            problem_type: "about:blank".to_owned(),
+            title: status.canonical_reason().unwrap_or("Error").to_owned(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code: self.code(),
+            request_id: request_id::current(),
+            errors: match self {
+                AppError::InvalidFields(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::TokenCreation => AppError::Internal(err.to_string()),
+            _ => AppError::Unauthorized(err.to_string()),
+        }
+    }
+}
+
+impl From<validator::ValidationErrors> for AppError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        AppError::InvalidFields(field_errors(&errors))
+    }
+}
+
+/// Flattens nested validation errors into one entry per failed rule,
+/// sorted by field path.
+pub fn field_errors(errors: &validator::ValidationErrors) -> Vec<FieldError> {
+    let mut fields = Vec::new();
+    collect_field_errors("", errors, &mut fields);
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+fn collect_field_errors(
+    prefix: &str,
+    errors: &validator::ValidationErrors,
+    out: &mut Vec<FieldError>,
+) {
+    use validator::ValidationErrorsKind;
+
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                out.extend(errors.iter().map(|error| FieldError {
+                    field: path.clone(),
+                    code: error.code.to_string(),
+                    message: field_error_message(error),
+                }));
+            }
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(&path, nested, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(&format!("{}[{}]", path, index), nested, out);
+                }
+            }
+        }
+    }
+}
+
+/// The rule's own message, or one derived from the built-in rule's parameters.
+fn field_error_message(error: &validator::ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let min = error.params.get("min");
+    let max = error.params.get("max");
+    match (error.code.as_ref(), min, max) {
+        ("length", Some(min), Some(max)) => {
+            format!("Must be between {} and {} characters", min, max)
+        }
+        ("length", Some(min), None) => format!("Must be at least {} characters", min),
+        ("length", None, Some(max)) => format!("Must be at most {} characters", max),
+        ("range", Some(min), Some(max)) => format!("Must be between {} and {}", min, max),
+        ("range", Some(min), None) => format!("Must be at least {}", min),
+        ("range", None, Some(max)) => format!("Must be at most {}", max),
+        ("email", _, _) => "Must be a valid email address".to_owned(),
+        _ => "Is invalid".to_owned(),
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(err: bcrypt::BcryptError) -> Self {
+        AppError::Internal(err.to_string())
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::InsufficientFunds => StatusCode::BAD_REQUEST,
+            AppError::ValidationError(_) | AppError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let problem = self.problem();
+        if self.status_code().is_server_error() {
+            // Runs inside the request span, which carries the request id.
+            tracing::error!(error = %self, "Request failed");
+        }
+
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::RateLimited(retry_after) = self {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+
+        response.content_type(PROBLEM_CONTENT_TYPE).json(problem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_and_statuses() {
+        let err = AppError::NotFound("Account".into());
+        assert_eq!(err.code(), "not_found");
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(err.detail(), "Account not found");
+
+        let err = AppError::ValidationError("Amount must be positive".into());
+        assert_eq!(err.code(), "validation_error");
+        assert_eq!(err.detail(), "Amount must be positive");
+    }
+
+    #[test]
+    fn test_internal_errors_are_not_leaked() {
+        let err = AppError::DatabaseError(sqlx::Error::Protocol(
+            "duplicate key value violates unique constraint \"users_email_key\"".into(),
+        ));
+        let problem = err.problem();
+        assert_eq!(problem.status, 500);
+        assert_eq!(problem.code, "internal_error");
+        assert_eq!(problem.detail, "An internal error occurred");
+    }
+
+    #[test]
+    fn test_validation_errors_list_each_field() {
+        let mut errors = validator::ValidationErrors::new();
+        let mut length = validator::ValidationError::new("length");
+        length.add_param("max".into(), &255);
+        errors.add("description", length);
+        errors.add(
+            "amount",
+            validator::ValidationError::new("range").with_message("Amount is too large".into()),
+        );
+
+        let problem = AppError::from(errors).problem();
+        assert_eq!(problem.status, 400);
+        assert_eq!(problem.code, "validation_error");
+        assert_eq!(
+            problem.errors,
+            vec![
+                FieldError {
+                    field: "amount".into(),
+                    code: "range".into(),
+                    message: "Amount is too large".into(),
+                },
+                FieldError {
+                    field: "description".into(),
+                    code: "length".into(),
+                    message: "Must be at most 255 characters".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auth_errors_map_to_unauthorized() {
+        assert_eq!(AppError::from(AuthError::InvalidToken).code(), "unauthorized");
+        assert_eq!(AppError::from(AuthError::TokenCreation).code(), "internal_error");
+    }
 }
@@ -1,4 +1,11 @@
 pub mod auth;
 pub mod error;
+pub mod metrics;
 pub mod models;
-pub mod routes;
\ No newline at end of file
+pub mod notifier;
+pub mod openapi;
+pub mod request_id;
+pub mod routes;
+pub mod settings;
+pub mod telemetry;
+pub mod validation;
\ No newline at end of file
@@ -1,34 +1,95 @@
-use actix_web::{App, HttpServer, middleware::Logger, web};
+use actix_web::{App, HttpServer, middleware::from_fn, web};
 use sqlx::postgres::PgPoolOptions;
-use std::env;
+use std::process;
+use std::time::Duration;
 
 use banking_api::auth::jwt::JwtService;
-use banking_api::routes;
+use banking_api::models::Hold;
+use banking_api::settings::Settings;
+use banking_api::{metrics, notifier, request_id, routes, telemetry};
 
+/// How often pending holds are checked for expiry.
+const HOLD_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
 
+/// Logs why the server can't start and exits.
+fn fail(message: impl std::fmt::Display) -> ! {
+    tracing::error!("{}", message);
+    process::exit(1);
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
-    env_logger::init();
 
-    let jwt_service = JwtService::from_env();
+    // Logging is configured by the settings, so their errors go to stderr.
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    telemetry::init(&settings.log);
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let jwt_service = JwtService::from_settings(&settings.jwt)
+        .unwrap_or_else(|e| fail(format!("Failed to load JWT keys: {}", e)));
+    let database = &settings.database;
     let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+        .max_connections(database.max_connections)
+        .min_connections(database.min_connections)
+        .acquire_timeout(Duration::from_secs(database.acquire_timeout_seconds))
+        .idle_timeout(Duration::from_secs(database.idle_timeout_seconds))
+        .connect(&database.url)
         .await
-        .expect("Failed to create pool");
+        .unwrap_or_else(|e| fail(format!("Failed to connect to the database: {}", e)));
+
+    let expiry_pool = pool.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(HOLD_EXPIRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            match Hold::expire_due(&expiry_pool).await {
+                Ok(0) => {}
+                Ok(expired) => tracing::info!(expired, "Expired pending holds"),
+                Err(e) => tracing::error!(error = %e, "Failed to expire holds"),
+            }
+        }
+    });
+
+    let jwt_service = web::Data::new(jwt_service);
+    let pool = web::Data::new(pool);
+    let login_policy = web::Data::new(settings.login.clone());
+    let limits = web::Data::new(settings.limits.clone());
+    let notifier = web::Data::from(notifier::from_settings(&settings.notifier));
 
-    HttpServer::new(move || {
+    let metrics_pool = pool.clone();
+    let metrics_server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(jwt_service.clone()))
-            .wrap(Logger::default())
-            .app_data(web::Data::new(pool.clone()))
-            .configure(routes::configure)
+            .app_data(metrics_pool.clone())
+            .configure(routes::configure_metrics)
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+    .workers(1);
+
+    let server = settings.server;
+    let mut http_server = HttpServer::new(move || {
+        App::new()
+            .app_data(jwt_service.clone())
+            .app_data(pool.clone())
+            .app_data(login_policy.clone())
+            .app_data(limits.clone())
+            .app_data(notifier.clone())
+            .wrap(from_fn(metrics::track))
+            .wrap(from_fn(telemetry::trace_request))
+            .wrap(from_fn(request_id::assign))
+            .configure(routes::configure)
+    });
+    if let Some(workers) = server.workers {
+        http_server = http_server.workers(workers);
+    }
+    tracing::info!(host = %server.host, port = server.port, "Listening");
+    let http_server = http_server.bind((server.host.as_str(), server.port))?.run();
+
+    let metrics = settings.metrics;
+    tracing::info!(host = %metrics.host, port = metrics.port, "Serving metrics");
+    let metrics_server = metrics_server.bind((metrics.host.as_str(), metrics.port))?.run();
+
+    futures::try_join!(http_server, metrics_server)?;
+    Ok(())
 }
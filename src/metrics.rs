@@ -0,0 +1,266 @@
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{Method, StatusCode},
+    middleware::Next,
+};
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use crate::error::AppError;
+use crate::models::{LoginOutcome, Transaction};
+
+/// Route label for requests that matched no route, so that scanners probing
+/// random paths can't blow up the number of series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Method label for anything else: actix accepts arbitrary extension methods,
+/// which would otherwise each get their own series.
+const OTHER_METHOD: &str = "other";
+
+const STANDARD_METHODS: [Method; 9] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::CONNECT,
+    Method::OPTIONS,
+    Method::TRACE,
+    Method::PATCH,
+];
+
+/// The process-wide metrics, served from `/metrics`.
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Prometheus collectors for HTTP traffic, the connection pool and business
+/// events. Business events are counted once their database transaction has
+/// committed.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_max_connections: IntGauge,
+    transactions: IntCounterVec,
+    transaction_amount: IntCounterVec,
+    insufficient_funds: IntCounterVec,
+    login_attempts: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let http_labels = &["method", "route", "status"];
+        Metrics {
+            http_requests: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("http_requests_total", "HTTP requests handled"),
+                    http_labels,
+                ),
+            ),
+            http_request_duration: register(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+                    http_labels,
+                ),
+            ),
+            db_pool_connections: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("db_pool_connections", "Open database connections by state"),
+                    &["state"],
+                ),
+            ),
+            db_pool_max_connections: register(
+                &registry,
+                IntGauge::new("db_pool_max_connections", "Configured pool size"),
+            ),
+            transactions: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("transactions_total", "Transactions posted"),
+                    &["transaction_type"],
+                ),
+            ),
+            transaction_amount: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "transaction_amount_total",
+                        "Sum of posted transaction amounts, in minor units",
+                    ),
+                    &["transaction_type"],
+                ),
+            ),
+            insufficient_funds: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "insufficient_funds_rejections_total",
+                        "Debits, transfers and holds rejected for insufficient funds",
+                    ),
+                    &["route"],
+                ),
+            ),
+            login_attempts: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("login_attempts_total", "Password login attempts by outcome"),
+                    &["outcome"],
+                ),
+            ),
+            registry,
+        }
+    }
+
+    fn observe_response(&self, method: &Method, route: &str, status: StatusCode, elapsed: Duration) {
+        let method = if STANDARD_METHODS.contains(method) {
+            method.as_str()
+        } else {
+            OTHER_METHOD
+        };
+        let labels = [method, route, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn observe_error(&self, route: &str, error: Option<&AppError>) {
+        if let Some(AppError::InsufficientFunds) = error {
+            self.insufficient_funds.with_label_values(&[route]).inc();
+        }
+    }
+
+    fn record_transaction(&self, transaction: &Transaction) {
+        let labels = [transaction.transaction_type.as_str()];
+        self.transactions.with_label_values(&labels).inc();
+        self.transaction_amount
+            .with_label_values(&labels)
+            .inc_by(transaction.amount.unsigned_abs());
+    }
+
+    fn record_login(&self, outcome: LoginOutcome) {
+        self.login_attempts.with_label_values(&[outcome.as_str()]).inc();
+    }
+
+    /// The current values in the Prometheus text format, with the pool
+    /// gauges sampled from `pool`.
+    pub fn render(&self, pool: &sqlx::PgPool) -> Result<String, AppError> {
+        let size = i64::from(pool.size());
+        let idle = (pool.num_idle() as i64).min(size);
+        self.db_pool_connections.with_label_values(&["idle"]).set(idle);
+        self.db_pool_connections.with_label_values(&["in_use"]).set(size - idle);
+        self.db_pool_max_connections
+            .set(i64::from(pool.options().get_max_connections()));
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        String::from_utf8(buffer).map_err(|e| AppError::Internal(e.to_string()))
+    }
+}
+
+fn register<M: Collector + Clone + 'static>(
+    registry: &Registry,
+    metric: prometheus::Result<M>,
+) -> M {
+    let metric = metric.expect("valid metric definition");
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
+/// The metrics of this process.
+pub fn global() -> &'static Metrics {
+    &METRICS
+}
+
+/// Counts a transaction that has been committed.
+pub fn record_transaction(transaction: &Transaction) {
+    METRICS.record_transaction(transaction);
+}
+
+pub fn record_login(outcome: LoginOutcome) {
+    METRICS.record_login(outcome);
+}
+
+/// Middleware that counts and times every request by route pattern, e.g.
+/// `/api/accounts/{id}`, and status.
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_owned());
+
+    let result = next.call(req).await;
+    // Errors from inner middleware (e.g. rejected credentials) arrive as
+    // `Err` and are only turned into responses further out.
+    let (status, error) = match &result {
+        Ok(res) => (res.status(), res.response().error()),
+        Err(e) => (e.as_response_error().status_code(), Some(e)),
+    };
+    METRICS.observe_response(&method, &route, status, started.elapsed());
+    METRICS.observe_error(&route, error.and_then(|e| e.as_error::<AppError>()));
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render_in_text_format() {
+        let metrics = Metrics::new();
+        metrics.observe_response(
+            &Method::GET,
+            "/api/accounts/{id}",
+            StatusCode::OK,
+            Duration::from_millis(12),
+        );
+        metrics.observe_response(
+            &Method::from_bytes(b"SCAN123").unwrap(),
+            UNMATCHED_ROUTE,
+            StatusCode::NOT_FOUND,
+            Duration::from_millis(1),
+        );
+        metrics.observe_error("/api/transactions", Some(&AppError::InsufficientFunds));
+        metrics.observe_error("/api/transactions", Some(&AppError::NotFound("Account".into())));
+        metrics.record_login(LoginOutcome::InvalidCredentials);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&metrics.registry.gather(), &mut buffer)
+            .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        assert!(text.contains(
+            r#"http_requests_total{method="GET",route="/api/accounts/{id}",status="200"} 1"#
+        ));
+        assert!(text.contains(r#"http_request_duration_seconds_count{method="GET""#));
+        assert!(text.contains(
+            r#"http_requests_total{method="other",route="unmatched",status="404"} 1"#
+        ));
+        assert!(!text.contains("SCAN123"));
+        assert!(text.contains(
+            r#"insufficient_funds_rejections_total{route="/api/transactions"} 1"#
+        ));
+        assert!(text.contains(r#"login_attempts_total{outcome="invalid_credentials"} 1"#));
+    }
+}
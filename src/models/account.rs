@@ -0,0 +1,267 @@
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::AccountBalance;
+use super::ledger::Ledger;
+
+/// Longest display name a user may give an account.
+pub const MAX_ACCOUNT_NAME_LENGTH: u64 = 64;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct Account {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub account_type: AccountType,
+    pub name: String,
+    pub status: AccountStatus,
+    pub balance: i64, // Stored in cents/pence, joined from account_balances
+    pub opened_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "account_type", rename_all = "lowercase")]
+pub enum AccountType {
+    Checking,
+    Savings,
+}
+
+impl AccountType {
+    pub fn default_name(&self) -> &'static str {
+        match self {
+            AccountType::Checking => "Checking",
+            AccountType::Savings => "Savings",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "account_status", rename_all = "lowercase")]
+pub enum AccountStatus {
+    Open,
+    Closed,
+}
+
+const SELECT_ACCOUNT: &str = r#"
+    SELECT a.id, a.user_id, a.account_type, a.name, a.status, b.balance, a.opened_at, a.closed_at
+    FROM accounts a
+    JOIN account_balances b ON b.account_id = a.id
+"#;
+
+impl Account {
+    /// Opens a new account with a zero balance for `user_id`.
+    #[instrument(skip_all, name = "Account::open", fields(user_id = %user_id))]
+    pub async fn open(
+        user_id: Uuid,
+        account_type: AccountType,
+        name: Option<String>,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        let mut tx = pool.begin().await?;
+        let (account, _) = Self::open_in_tx(user_id, account_type, name, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(account)
+    }
+
+    /// Opens an account and its balance row inside an existing transaction.
+    #[instrument(skip_all, name = "Account::open_in_tx", fields(user_id = %user_id))]
+    pub(crate) async fn open_in_tx(
+        user_id: Uuid,
+        account_type: AccountType,
+        name: Option<String>,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(Self, AccountBalance), AppError> {
+        let name = name
+            .map(|n| n.trim().to_owned())
+            .unwrap_or_else(|| account_type.default_name().to_owned());
+        if name.is_empty() || name.chars().count() as u64 > MAX_ACCOUNT_NAME_LENGTH {
+            return Err(AppError::ValidationError(format!(
+                "Account name must be between 1 and {} characters",
+                MAX_ACCOUNT_NAME_LENGTH
+            )));
+        }
+
+        let account_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO accounts (user_id, account_type, name)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+        )
+        .bind(user_id)
+        .bind(account_type)
+        .bind(&name)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ledger::open_customer_account(account_id, &name, tx).await?;
+
+        let balance = sqlx::query_as::<_, AccountBalance>(
+            r#"
+            INSERT INTO account_balances (user_id, account_id, balance, last_updated)
+            VALUES ($1, $2, 0, NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(account_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        let account = sqlx::query_as::<_, Self>(&format!("{SELECT_ACCOUNT} WHERE a.id = $1"))
+            .bind(account_id)
+            .fetch_one(&mut **tx)
+            .await?;
+
+        Ok((account, balance))
+    }
+
+    /// Fetches an account, scoped to its owner.
+    #[instrument(
+        skip_all,
+        name = "Account::get_by_id",
+        fields(account_id = %account_id, user_id = %user_id),
+    )]
+    pub async fn get_by_id(
+        account_id: Uuid,
+        user_id: Uuid,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>(&format!(
+            "{SELECT_ACCOUNT} WHERE a.id = $1 AND a.user_id = $2"
+        ))
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Account".into()))
+    }
+
+    /// Fetches an account regardless of owner. For staff tooling only.
+    #[instrument(skip_all, name = "Account::find", fields(account_id = %account_id))]
+    pub async fn find(account_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>(&format!("{SELECT_ACCOUNT} WHERE a.id = $1"))
+            .bind(account_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Account".into()))
+    }
+
+    #[instrument(skip_all, name = "Account::get_by_user", fields(user_id = %user_id))]
+    pub async fn get_by_user(user_id: Uuid, pool: &sqlx::PgPool) -> Result<Vec<Self>, AppError> {
+        let accounts = sqlx::query_as::<_, Self>(&format!(
+            "{SELECT_ACCOUNT} WHERE a.user_id = $1 ORDER BY a.opened_at, a.id"
+        ))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(accounts)
+    }
+
+    /// The user's oldest open account. Used when no account is specified,
+    /// e.g. as the destination of an incoming transfer.
+    #[instrument(skip_all, name = "Account::get_primary", fields(user_id = %user_id))]
+    pub async fn get_primary(user_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>(&format!(
+            "{SELECT_ACCOUNT} WHERE a.user_id = $1 AND a.status = 'open' ORDER BY a.opened_at, a.id LIMIT 1"
+        ))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Open account".into()))
+    }
+
+    /// Closes an account. Only accounts with a zero balance can be closed.
+    #[instrument(
+        skip_all,
+        name = "Account::close",
+        fields(account_id = %account_id, user_id = %user_id),
+    )]
+    pub async fn close(
+        account_id: Uuid,
+        user_id: Uuid,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        let mut tx = pool.begin().await?;
+
+        // Lock the balance row so no transaction can land while we close.
+        let balance = sqlx::query_as::<_, AccountBalance>(
+            r#"
+            SELECT b.* FROM account_balances b
+            JOIN accounts a ON a.id = b.account_id
+            WHERE b.account_id = $1 AND a.user_id = $2
+            FOR UPDATE OF b
+            "#,
+        )
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Account".into()))?;
+
+        if balance.balance != 0 {
+            return Err(AppError::ValidationError(
+                "Account balance must be zero before closing".into(),
+            ));
+        }
+
+        let closed = sqlx::query(
+            r#"
+            UPDATE accounts
+            SET status = 'closed', closed_at = NOW()
+            WHERE id = $1 AND status = 'open'
+            "#,
+        )
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if closed.rows_affected() == 0 {
+            return Err(AppError::ValidationError("Account is already closed".into()));
+        }
+
+        let account = sqlx::query_as::<_, Self>(&format!("{SELECT_ACCOUNT} WHERE a.id = $1"))
+            .bind(account_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_type_serialization() {
+        assert_eq!(serde_json::to_string(&AccountType::Checking).unwrap(), "\"checking\"");
+        assert_eq!(serde_json::to_string(&AccountType::Savings).unwrap(), "\"savings\"");
+
+        let savings: AccountType = serde_json::from_str("\"savings\"").unwrap();
+        assert_eq!(savings, AccountType::Savings);
+    }
+
+    #[test]
+    fn test_account_status_serialization() {
+        assert_eq!(serde_json::to_string(&AccountStatus::Open).unwrap(), "\"open\"");
+        assert_eq!(serde_json::to_string(&AccountStatus::Closed).unwrap(), "\"closed\"");
+    }
+
+    #[test]
+    fn test_default_names() {
+        assert_eq!(AccountType::Checking.default_name(), "Checking");
+        assert_eq!(AccountType::Savings.default_name(), "Savings");
+    }
+}
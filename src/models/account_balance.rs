@@ -1,46 +1,134 @@
 use crate::error::AppError;
+use crate::models::ledger::Ledger;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Postgres, Transaction};
+use tracing::instrument;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
 pub struct AccountBalance {
     pub id: Uuid,
     pub user_id: Uuid,
+    pub account_id: Uuid,
     pub balance: i64, // Stored in cents/pence
     pub last_updated: DateTime<Utc>,
+    pub held: i64, // Reserved by pending holds; part of the ledger balance but not spendable
+}
+
+/// Result of checking an account's stored balance against its ledger postings.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BalanceVerification {
+    pub account_id: Uuid,
+    pub balance: i64,
+    pub ledger_balance: i64,
+    pub consistent: bool,
 }
 
 impl AccountBalance {
-    pub async fn get_balance(user_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
-        let balance = sqlx::query_as!(
-            Self,
-            "SELECT * FROM account_balances WHERE user_id = $1",
-            user_id
+    /// Ledger balance minus funds reserved by pending holds.
+    pub fn available(&self) -> i64 {
+        self.balance - self.held
+    }
+
+    #[instrument(
+        skip_all,
+        name = "AccountBalance::get_balance",
+        fields(account_id = %account_id, user_id = %user_id),
+    )]
+    pub async fn get_balance(
+        account_id: Uuid,
+        user_id: Uuid,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM account_balances WHERE account_id = $1 AND user_id = $2",
+        )
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Account".into()))
+    }
+
+    /// Recomputes the balance from the ledger and compares it with this projection.
+    #[instrument(
+        skip_all,
+        name = "AccountBalance::verify",
+        fields(account_id = %account_id, user_id = %user_id),
+    )]
+    pub async fn verify(
+        account_id: Uuid,
+        user_id: Uuid,
+        pool: &sqlx::PgPool,
+    ) -> Result<BalanceVerification, AppError> {
+        let projection = Self::get_balance(account_id, user_id, pool).await?;
+        let ledger_balance = Ledger::customer_balance(account_id, pool).await?;
+
+        Ok(BalanceVerification {
+            account_id,
+            balance: projection.balance,
+            ledger_balance,
+            consistent: projection.balance == ledger_balance,
+        })
+    }
+
+    /// Locks the balance rows of several accounts in a deterministic
+    /// (account_id) order so concurrent multi-account operations cannot deadlock.
+    #[instrument(skip_all, name = "AccountBalance::lock_for_update")]
+    pub async fn lock_for_update(
+        account_ids: &[Uuid],
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<Self>, AppError> {
+        let mut ordered = account_ids.to_vec();
+        ordered.sort();
+        ordered.dedup();
+
+        let balances = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM account_balances
+            WHERE account_id = ANY($1)
+            ORDER BY account_id
+            FOR UPDATE
+            "#,
         )
-        .fetch_one(pool)
+        .bind(&ordered)
+        .fetch_all(&mut **tx)
         .await?;
 
-        Ok(balance)
+        Ok(balances)
     }
 
+    #[instrument(
+        skip_all,
+        name = "AccountBalance::update_balance",
+        fields(account_id = %account_id),
+    )]
     async fn update_balance(
-        user_id: Uuid,
+        account_id: Uuid,
         amount: i64,
         operation: impl Fn(i64, i64) -> i64,
         tx: &mut Transaction<'_, Postgres>,
     ) -> Result<Self, AppError> {
+        // The account status is checked under the row lock so a concurrent close can't race us.
         let current = sqlx::query_as::<_, Self>(
-            "SELECT * FROM account_balances WHERE user_id = $1 FOR UPDATE",
+            r#"
+            SELECT b.* FROM account_balances b
+            JOIN accounts a ON a.id = b.account_id
+            WHERE b.account_id = $1 AND a.status = 'open'
+            FOR UPDATE OF b
+            "#,
         )
-        .bind(user_id)
-        .fetch_one(&mut **tx)
-        .await?;
+        .bind(account_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Open account".into()))?;
 
         let new_balance = operation(current.balance, amount);
 
-        if new_balance < 0 {
+        // Funds reserved by holds cannot be spent by other debits.
+        if new_balance < current.held {
             return Err(AppError::InsufficientFunds);
         }
 
@@ -48,32 +136,97 @@ impl AccountBalance {
             r#"
             UPDATE account_balances
             SET balance = $1, last_updated = NOW()
-            WHERE user_id = $2
+            WHERE account_id = $2
             RETURNING *
             "#,
         )
         .bind(new_balance)
-        .bind(user_id)
+        .bind(account_id)
         .fetch_one(&mut **tx)
         .await?;
 
         Ok(updated)
     }
 
+    /// Reserves `amount` of the available balance without changing the ledger balance.
+    #[instrument(skip_all, name = "AccountBalance::place_hold", fields(account_id = %account_id))]
+    pub async fn place_hold(
+        account_id: Uuid,
+        amount: i64,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        let current = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT b.* FROM account_balances b
+            JOIN accounts a ON a.id = b.account_id
+            WHERE b.account_id = $1 AND a.status = 'open'
+            FOR UPDATE OF b
+            "#,
+        )
+        .bind(account_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Open account".into()))?;
+
+        if current.available() < amount {
+            return Err(AppError::InsufficientFunds);
+        }
+
+        Self::adjust_held(account_id, amount, tx).await
+    }
+
+    /// Returns previously reserved funds to the available balance.
+    #[instrument(skip_all, name = "AccountBalance::release_hold", fields(account_id = %account_id))]
+    pub async fn release_hold(
+        account_id: Uuid,
+        amount: i64,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        Self::adjust_held(account_id, -amount, tx).await
+    }
+
+    #[instrument(
+        skip_all,
+        name = "AccountBalance::adjust_held",
+        fields(account_id = %account_id),
+    )]
+    async fn adjust_held(
+        account_id: Uuid,
+        delta: i64,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        let updated = sqlx::query_as::<_, Self>(
+            r#"
+            UPDATE account_balances
+            SET held = held + $1, last_updated = NOW()
+            WHERE account_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(delta)
+        .bind(account_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(updated)
+    }
+
+    #[instrument(skip_all, name = "AccountBalance::credit", fields(account_id = %account_id))]
     pub async fn credit(
-        user_id: Uuid,
+        account_id: Uuid,
         amount: i64,
         tx: &mut Transaction<'_, Postgres>,
     ) -> Result<Self, AppError> {
-        Self::update_balance(user_id, amount, |balance, amount| balance + amount, tx).await
+        Self::update_balance(account_id, amount, |balance, amount| balance + amount, tx).await
     }
 
+    #[instrument(skip_all, name = "AccountBalance::debit", fields(account_id = %account_id))]
     pub async fn debit(
-        user_id: Uuid,
+        account_id: Uuid,
         amount: i64,
         tx: &mut Transaction<'_, Postgres>,
     ) -> Result<Self, AppError> {
-        Self::update_balance(user_id, amount, |balance, amount| balance - amount, tx).await
+        Self::update_balance(account_id, amount, |balance, amount| balance - amount, tx).await
     }
 }
 
@@ -103,19 +256,24 @@ mod tests {
     fn test_account_balance_struct_fields() {
         let id = Uuid::new_v4();
         let user_id = Uuid::new_v4();
+        let account_id = Uuid::new_v4();
         let balance = 2000;
         let now = Utc::now();
 
         let ab = AccountBalance {
             id,
             user_id,
+            account_id,
             balance,
             last_updated: now,
+            held: 500,
         };
 
         assert_eq!(ab.id, id);
         assert_eq!(ab.user_id, user_id);
+        assert_eq!(ab.account_id, account_id);
         assert_eq!(ab.balance, 2000);
         assert_eq!(ab.last_updated, now);
+        assert_eq!(ab.available(), 1500);
     }
 }
\ No newline at end of file
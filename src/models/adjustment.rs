@@ -0,0 +1,156 @@
+use crate::error::AppError;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::ledger::{Ledger, LedgerAccount, PostingLine, SystemAccount};
+use super::transaction::NewTransaction;
+use super::{Account, Transaction, TransactionType};
+
+/// Longest reason a staff member may give for an adjustment.
+pub const MAX_REASON_LENGTH: u64 = 500;
+/// What the customer sees on an adjustment. The reason stays internal.
+pub const ADJUSTMENT_DESCRIPTION: &str = "Manual adjustment";
+
+/// Audit record of a manual balance correction made by staff.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct AdjustmentRecord {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub transaction_id: Uuid,
+    pub admin_id: Uuid,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A manual adjustment together with the transaction it posted.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Adjustment {
+    #[serde(flatten)]
+    pub record: AdjustmentRecord,
+    pub transaction: Transaction,
+}
+
+impl Adjustment {
+    /// Credits or debits any customer account, balanced against the
+    /// `adjustments` system account. A reason is mandatory and is kept
+    /// alongside the id of the staff member who made the change; the
+    /// customer only sees a fixed description. Staff cannot adjust their
+    /// own accounts.
+    #[instrument(
+        skip_all,
+        name = "Adjustment::create",
+        fields(admin_id = %admin_id, account_id = %account_id),
+    )]
+    pub async fn create(
+        admin_id: Uuid,
+        account_id: Uuid,
+        amount: i64,
+        transaction_type: TransactionType,
+        reason: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        if amount <= 0 {
+            return Err(AppError::ValidationError("Amount must be positive".into()));
+        }
+        let reason = Self::validate_reason(reason)?;
+        let account = Account::find(account_id, pool).await?;
+        Self::check_not_own(admin_id, account.user_id)?;
+
+        let mut tx = pool.begin().await?;
+
+        let new = NewTransaction {
+            user_id: account.user_id,
+            account_id,
+            amount,
+            transaction_type,
+            description: Some(ADJUSTMENT_DESCRIPTION.to_owned()),
+            transfer_id: None,
+            reverses_transaction_id: None,
+        };
+        let posting = new.posting();
+        let entry = Ledger::post(
+            new.description.as_deref(),
+            &[
+                posting,
+                PostingLine::credit(
+                    LedgerAccount::System(SystemAccount::Adjustments),
+                    -posting.amount,
+                ),
+            ],
+            &mut tx,
+        )
+        .await?;
+        let transaction = Transaction::record(new, entry.id, &mut tx).await?;
+
+        let record = sqlx::query_as::<_, AdjustmentRecord>(
+            r#"
+            INSERT INTO adjustments (account_id, transaction_id, admin_id, reason)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
+            "#,
+        )
+        .bind(account_id)
+        .bind(transaction.id)
+        .bind(admin_id)
+        .bind(reason)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        metrics::record_transaction(&transaction);
+
+        Ok(Adjustment { record, transaction })
+    }
+
+    /// Staff changes to their own money need a second pair of hands.
+    pub(crate) fn check_not_own(admin_id: Uuid, owner_id: Uuid) -> Result<(), AppError> {
+        if admin_id == owner_id {
+            return Err(AppError::Forbidden(
+                "Staff cannot adjust or reverse their own transactions".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn validate_reason(reason: &str) -> Result<&str, AppError> {
+        let reason = reason.trim();
+        if reason.is_empty() || reason.chars().count() as u64 > MAX_REASON_LENGTH {
+            return Err(AppError::ValidationError(format!(
+                "reason must be between 1 and {} characters",
+                MAX_REASON_LENGTH
+            )));
+        }
+        Ok(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_is_required() {
+        assert!(Adjustment::validate_reason("").is_err());
+        assert!(Adjustment::validate_reason("   ").is_err());
+        assert!(Adjustment::validate_reason(&"x".repeat(MAX_REASON_LENGTH as usize + 1)).is_err());
+        assert_eq!(
+            Adjustment::validate_reason("  Goodwill credit ").unwrap(),
+            "Goodwill credit"
+        );
+    }
+
+    #[test]
+    fn test_staff_cannot_adjust_their_own_accounts() {
+        let admin_id = Uuid::new_v4();
+        assert!(matches!(
+            Adjustment::check_not_own(admin_id, admin_id),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(Adjustment::check_not_own(admin_id, Uuid::new_v4()).is_ok());
+    }
+}
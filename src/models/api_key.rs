@@ -0,0 +1,196 @@
+use crate::auth::scopes::Scope;
+use crate::auth::tokens::{generate_opaque_token, hash_opaque_token};
+use crate::error::AppError;
+use crate::settings::Limits;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Every API key starts with this, so they are easy to tell from JWTs and
+/// to spot in leaked source code.
+pub const API_KEY_PREFIX: &str = "bk_";
+/// Characters of the key kept in plain text to identify it in listings.
+pub const DISPLAY_PREFIX_LENGTH: usize = 11;
+pub const MAX_API_KEY_NAME_LENGTH: u64 = 64;
+/// Default for `limits.default_api_key_ttl_days`.
+pub const DEFAULT_API_KEY_TTL_DAYS: i64 = 90;
+pub const MAX_API_KEY_TTL_DAYS: i64 = 365;
+/// Default for `limits.max_api_keys_per_user`.
+pub const MAX_API_KEYS_PER_USER: i64 = 25;
+/// `last_used_at` is refreshed at most this often, to keep reads cheap.
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A newly created key. The secret is only ever returned here.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    pub secret: String,
+}
+
+impl ApiKey {
+    #[instrument(skip_all, name = "ApiKey::create", fields(user_id = %user_id))]
+    pub async fn create(
+        user_id: Uuid,
+        name: &str,
+        scopes: &[Scope],
+        ttl_days: Option<i64>,
+        limits: &Limits,
+        pool: &sqlx::PgPool,
+    ) -> Result<CreatedApiKey, AppError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::ValidationError("name must not be blank".into()));
+        }
+        if let Some(scope) = scopes.iter().find(|s| !s.grantable_to_api_keys()) {
+            return Err(AppError::ValidationError(format!(
+                "API keys cannot be granted {}",
+                scope
+            )));
+        }
+        let scopes = Scope::normalize(scopes);
+
+        let active = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM api_keys
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+        if active >= limits.max_api_keys_per_user {
+            return Err(AppError::Conflict(format!(
+                "A user may have at most {} active API keys",
+                limits.max_api_keys_per_user
+            )));
+        }
+
+        let secret = format!("{}{}", API_KEY_PREFIX, generate_opaque_token());
+        let ttl = Duration::days(ttl_days.unwrap_or(limits.default_api_key_ttl_days));
+        let key = sqlx::query_as::<_, Self>(
+            r#"
+            INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(&secret[..DISPLAY_PREFIX_LENGTH])
+        .bind(hash_opaque_token(&secret))
+        .bind(&scopes)
+        .bind(Utc::now() + ttl)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(CreatedApiKey { key, secret })
+    }
+
+    /// The user's keys, newest first, including revoked and expired ones.
+    #[instrument(skip_all, name = "ApiKey::get_by_user", fields(user_id = %user_id))]
+    pub async fn get_by_user(user_id: Uuid, pool: &sqlx::PgPool) -> Result<Vec<Self>, AppError> {
+        let keys = sqlx::query_as::<_, Self>(
+            "SELECT * FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    #[instrument(skip_all, name = "ApiKey::revoke", fields(id = %id, user_id = %user_id))]
+    pub async fn revoke(id: Uuid, user_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>(
+            r#"
+            UPDATE api_keys SET revoked_at = COALESCE(revoked_at, NOW())
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("API key".into()))
+    }
+
+    /// The live key matching `secret`, if any, recording that it was used.
+    #[instrument(skip_all, name = "ApiKey::authenticate")]
+    pub async fn authenticate(secret: &str, pool: &sqlx::PgPool) -> Result<Option<Self>, AppError> {
+        let key = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM api_keys
+            WHERE key_hash = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+        )
+        .bind(hash_opaque_token(secret))
+        .fetch_optional(pool)
+        .await?;
+        let Some(key) = key else {
+            return Ok(None);
+        };
+
+        let stale = key
+            .last_used_at
+            .is_none_or(|at| at < Utc::now() - Duration::seconds(LAST_USED_RESOLUTION_SECONDS));
+        if stale {
+            sqlx::query("UPDATE api_keys SET last_used_at = NOW() WHERE id = $1")
+                .bind(key.id)
+                .execute(pool)
+                .await?;
+        }
+
+        Ok(Some(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_never_includes_the_secret() {
+        let now = Utc::now();
+        let key = ApiKey {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            name: "nightly export".into(),
+            prefix: "bk_1a2b3c4d".into(),
+            scopes: vec![Scope::TransactionsRead],
+            expires_at: now,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: now,
+        };
+
+        let listed = serde_json::to_value(&key).unwrap();
+        assert!(listed.get("secret").is_none());
+        assert_eq!(listed["scopes"], serde_json::json!(["transactions:read"]));
+
+        let created = serde_json::to_value(CreatedApiKey {
+            key,
+            secret: "bk_secret".into(),
+        })
+        .unwrap();
+        assert_eq!(created["secret"], "bk_secret");
+        assert_eq!(created["name"], "nightly export");
+    }
+}
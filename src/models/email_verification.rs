@@ -0,0 +1,155 @@
+use crate::auth::tokens::{generate_opaque_token, hash_opaque_token};
+use crate::error::AppError;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::FromRow;
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::User;
+
+/// How long an email verification token stays valid.
+pub const EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, FromRow)]
+struct VerificationTokenRow {
+    user_id: Uuid,
+    email: String,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+}
+
+pub struct EmailVerification;
+
+impl EmailVerification {
+    /// Issues a token confirming `email` for the user. Earlier unused tokens
+    /// are withdrawn, so only the latest message works.
+    #[instrument(skip_all, name = "EmailVerification::issue", fields(user_id = %user_id))]
+    pub async fn issue(user_id: Uuid, email: &str, pool: &sqlx::PgPool) -> Result<String, AppError> {
+        let token = generate_opaque_token();
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE email_verification_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO email_verification_tokens (user_id, email, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(user_id)
+        .bind(email)
+        .bind(hash_opaque_token(&token))
+        .bind(Utc::now() + Duration::hours(EMAIL_VERIFICATION_TTL_HOURS))
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(token)
+    }
+
+    /// The address a resend should go to: a pending change, or the current
+    /// email if it was never confirmed.
+    pub fn address_to_confirm(user: &User) -> Result<&str, AppError> {
+        match (&user.pending_email, user.is_email_verified()) {
+            (Some(pending), _) => Ok(pending),
+            (None, false) => Ok(&user.email),
+            (None, true) => Err(AppError::Conflict("Email address is already verified".into())),
+        }
+    }
+
+    /// Confirms the address a token was issued for. A pending address
+    /// replaces the current email at this point.
+    #[instrument(skip_all, name = "EmailVerification::confirm")]
+    pub async fn confirm(token: &str, pool: &sqlx::PgPool) -> Result<User, AppError> {
+        let invalid = || AppError::ValidationError("Invalid or expired verification token".into());
+        let mut tx = pool.begin().await?;
+
+        let verification = sqlx::query_as::<_, VerificationTokenRow>(
+            r#"
+            SELECT user_id, email, expires_at, used_at FROM email_verification_tokens
+            WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(hash_opaque_token(token))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(invalid)?;
+
+        if verification.used_at.is_some() || verification.expires_at <= Utc::now() {
+            return Err(invalid());
+        }
+
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET email = $2,
+                pending_email = NULL,
+                email_verified_at = NOW(),
+                updated_at = NOW()
+            WHERE id = $1 AND (email = $2 OR pending_email = $2)
+            RETURNING *
+            "#,
+        )
+        .bind(verification.user_id)
+        .bind(&verification.email)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                AppError::Conflict("Email address is already in use".into())
+            }
+            e => e.into(),
+        })?
+        // The user has since asked for a different address.
+        .ok_or_else(invalid)?;
+
+        sqlx::query("UPDATE email_verification_tokens SET used_at = NOW() WHERE token_hash = $1")
+            .bind(hash_opaque_token(token))
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+
+    fn user(verified: bool, pending_email: Option<&str>) -> User {
+        let now = Utc::now();
+        User {
+            id: Uuid::new_v4(),
+            username: "alice".into(),
+            email: "alice@example.com".into(),
+            password_hash: "hashed".into(),
+            created_at: now,
+            updated_at: now,
+            role: Role::Customer,
+            email_verified_at: verified.then_some(now),
+            pending_email: pending_email.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_address_to_confirm() {
+        assert_eq!(
+            EmailVerification::address_to_confirm(&user(false, None)).unwrap(),
+            "alice@example.com"
+        );
+        assert_eq!(
+            EmailVerification::address_to_confirm(&user(true, Some("new@example.com"))).unwrap(),
+            "new@example.com"
+        );
+        assert!(EmailVerification::address_to_confirm(&user(true, None)).is_err());
+    }
+}
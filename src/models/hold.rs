@@ -0,0 +1,367 @@
+use crate::error::AppError;
+use crate::metrics;
+use crate::settings::Limits;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction as DbTransaction};
+use std::collections::BTreeMap;
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::transaction::NewTransaction;
+use super::{AccountBalance, Transaction, TransactionType, User};
+
+/// Default for `limits.default_hold_ttl_seconds`: how long an authorization
+/// stays valid when the caller doesn't say.
+pub const DEFAULT_HOLD_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+/// Upper bound on how long funds may be reserved.
+pub const MAX_HOLD_TTL_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+/// Funds reserved on an account ahead of settlement, card-authorization style.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct Hold {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub account_id: Uuid,
+    pub amount: i64,
+    pub captured_amount: i64,
+    pub status: HoldStatus,
+    pub description: Option<String>,
+    pub capture_transaction_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "hold_status", rename_all = "lowercase")]
+pub enum HoldStatus {
+    Pending,
+    Captured,
+    Voided,
+    Expired,
+}
+
+/// A captured hold together with the debit it settled into.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Capture {
+    pub hold: Hold,
+    pub transaction: Transaction,
+}
+
+impl Hold {
+    /// Reserves `amount` on the account. The available balance drops
+    /// immediately; the ledger balance only moves on capture.
+    #[instrument(
+        skip_all,
+        name = "Hold::authorize",
+        fields(user_id = %user_id, account_id = %account_id),
+    )]
+    pub async fn authorize(
+        user_id: Uuid,
+        account_id: Uuid,
+        amount: i64,
+        description: Option<String>,
+        ttl_seconds: Option<i64>,
+        limits: &Limits,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        if amount <= 0 {
+            return Err(AppError::ValidationError("Amount must be positive".into()));
+        }
+        let ttl = ttl_seconds.unwrap_or(limits.default_hold_ttl_seconds);
+        if !(1..=MAX_HOLD_TTL_SECONDS).contains(&ttl) {
+            return Err(AppError::ValidationError(format!(
+                "expires_in_seconds must be between 1 and {}",
+                MAX_HOLD_TTL_SECONDS
+            )));
+        }
+        // A hold is a pending debit, so it needs the same verified email.
+        User::ensure_verified(user_id, pool).await?;
+
+        let mut tx = pool.begin().await?;
+
+        let owned = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM accounts WHERE id = $1 AND user_id = $2)",
+        )
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !owned {
+            return Err(AppError::NotFound("Account".into()));
+        }
+
+        AccountBalance::place_hold(account_id, amount, &mut tx).await?;
+
+        let hold = sqlx::query_as::<_, Self>(
+            r#"
+            INSERT INTO holds (user_id, account_id, amount, description, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(account_id)
+        .bind(amount)
+        .bind(description)
+        .bind(Utc::now() + Duration::seconds(ttl))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(hold)
+    }
+
+    #[instrument(skip_all, name = "Hold::get_by_id", fields(id = %id, user_id = %user_id))]
+    pub async fn get_by_id(id: Uuid, user_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM holds WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Hold".into()))
+    }
+
+    #[instrument(skip_all, name = "Hold::get_by_user", fields(user_id = %user_id))]
+    pub async fn get_by_user(
+        user_id: Uuid,
+        status: Option<HoldStatus>,
+        pool: &sqlx::PgPool,
+    ) -> Result<Vec<Self>, AppError> {
+        let holds = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM holds
+            WHERE user_id = $1 AND ($2::hold_status IS NULL OR status = $2)
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(status)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(holds)
+    }
+
+    /// Settles a pending hold for `amount` (the full hold when `None`) and
+    /// releases whatever was not captured.
+    #[instrument(skip_all, name = "Hold::capture", fields(id = %id, user_id = %user_id))]
+    pub async fn capture(
+        id: Uuid,
+        user_id: Uuid,
+        amount: Option<i64>,
+        pool: &sqlx::PgPool,
+    ) -> Result<Capture, AppError> {
+        let mut tx = pool.begin().await?;
+        let hold = Self::lock_pending(id, user_id, &mut tx).await?;
+
+        let amount = amount.unwrap_or(hold.amount);
+        if amount <= 0 || amount > hold.amount {
+            return Err(AppError::ValidationError(format!(
+                "Capture amount must be between 1 and {}",
+                hold.amount
+            )));
+        }
+
+        // Release the whole reservation, then debit what is actually captured.
+        AccountBalance::release_hold(hold.account_id, hold.amount, &mut tx).await?;
+        let transaction = Transaction::record_cash(
+            NewTransaction {
+                user_id,
+                account_id: hold.account_id,
+                amount,
+                transaction_type: TransactionType::Debit,
+                description: hold.description.clone(),
+                transfer_id: None,
+                reverses_transaction_id: None,
+            },
+            &mut tx,
+        )
+        .await?;
+
+        let hold = sqlx::query_as::<_, Self>(
+            r#"
+            UPDATE holds
+            SET status = 'captured', captured_amount = $1, capture_transaction_id = $2,
+                updated_at = NOW()
+            WHERE id = $3
+            RETURNING *
+            "#,
+        )
+        .bind(amount)
+        .bind(transaction.id)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        metrics::record_transaction(&transaction);
+
+        Ok(Capture { hold, transaction })
+    }
+
+    /// Cancels a pending hold and returns its funds to the available balance.
+    #[instrument(skip_all, name = "Hold::void", fields(id = %id, user_id = %user_id))]
+    pub async fn void(id: Uuid, user_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        let mut tx = pool.begin().await?;
+        let hold = Self::lock_pending(id, user_id, &mut tx).await?;
+
+        AccountBalance::release_hold(hold.account_id, hold.amount, &mut tx).await?;
+        let hold = Self::set_status(id, HoldStatus::Voided, &mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(hold)
+    }
+
+    /// Expires every pending hold past its deadline, releasing the funds.
+    /// Returns how many holds were expired.
+    #[instrument(skip_all, name = "Hold::expire_due")]
+    pub async fn expire_due(pool: &sqlx::PgPool) -> Result<u64, AppError> {
+        let mut tx = pool.begin().await?;
+
+        // Holds being captured or voided right now are left for the next run.
+        let expired = sqlx::query_as::<_, (Uuid, i64)>(
+            r#"
+            WITH due AS (
+                SELECT id FROM holds
+                WHERE status = 'pending' AND expires_at <= NOW()
+                ORDER BY id
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE holds
+            SET status = 'expired', updated_at = NOW()
+            FROM due
+            WHERE holds.id = due.id
+            RETURNING holds.account_id, holds.amount
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Release in account order, like transfers and captures lock balances,
+        // so this can't deadlock against live traffic.
+        for (account_id, amount) in Self::totals_by_account(&expired) {
+            AccountBalance::release_hold(account_id, amount, &mut tx).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(expired.len() as u64)
+    }
+
+    /// Sums the amounts of expired holds per account, so each balance row is
+    /// updated once, in ascending account order.
+    fn totals_by_account(expired: &[(Uuid, i64)]) -> BTreeMap<Uuid, i64> {
+        let mut totals = BTreeMap::new();
+        for (account_id, amount) in expired {
+            *totals.entry(*account_id).or_insert(0) += amount;
+        }
+        totals
+    }
+
+    /// Locks a hold that can still be captured or voided. Holds past their
+    /// deadline are rejected; [`Hold::expire_due`] releases their funds.
+    async fn lock_pending(
+        id: Uuid,
+        user_id: Uuid,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        let hold = sqlx::query_as::<_, Self>(
+            "SELECT * FROM holds WHERE id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Hold".into()))?;
+
+        if hold.status != HoldStatus::Pending {
+            return Err(AppError::Conflict(format!(
+                "Hold is already {}",
+                hold.status.as_str()
+            )));
+        }
+        if hold.is_expired(Utc::now()) {
+            return Err(AppError::Conflict("Hold has expired".into()));
+        }
+
+        Ok(hold)
+    }
+
+    async fn set_status(
+        id: Uuid,
+        status: HoldStatus,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        let hold = sqlx::query_as::<_, Self>(
+            "UPDATE holds SET status = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
+        )
+        .bind(status)
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(hold)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+impl HoldStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HoldStatus::Pending => "pending",
+            HoldStatus::Captured => "captured",
+            HoldStatus::Voided => "voided",
+            HoldStatus::Expired => "expired",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold_status_serialization() {
+        assert_eq!(serde_json::to_string(&HoldStatus::Pending).unwrap(), "\"pending\"");
+        assert_eq!(serde_json::to_string(&HoldStatus::Captured).unwrap(), "\"captured\"");
+        assert_eq!(HoldStatus::Voided.as_str(), "voided");
+        assert_eq!(HoldStatus::Expired.as_str(), "expired");
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = Utc::now();
+        let hold = Hold {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            amount: 1000,
+            captured_amount: 0,
+            status: HoldStatus::Pending,
+            description: None,
+            capture_transaction_id: None,
+            expires_at: now,
+            created_at: now,
+            updated_at: now,
+        };
+
+        assert!(hold.is_expired(now));
+        assert!(!hold.is_expired(now - Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_expired_totals_are_grouped_and_sorted() {
+        let (a, b) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let totals = Hold::totals_by_account(&[(b, 300), (a, 100), (b, 50)]);
+        assert_eq!(totals.into_iter().collect::<Vec<_>>(), [(a, 100), (b, 350)]);
+    }
+}
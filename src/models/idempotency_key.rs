@@ -0,0 +1,133 @@
+use crate::error::AppError;
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Maximum accepted length of a client supplied `Idempotency-Key` header.
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// Outcome of claiming an idempotency key for a request.
+#[derive(Debug)]
+pub enum IdempotencyClaim {
+    /// The key was unused; the caller must execute the request and store its response.
+    New,
+    /// The key was already used for an identical request; this is the stored response.
+    Replay(serde_json::Value),
+}
+
+/// A client supplied idempotency key together with the hash of the request it guards.
+#[derive(Debug, Clone)]
+pub struct IdempotencyRequest {
+    pub key: String,
+    pub request_hash: String,
+}
+
+impl IdempotencyRequest {
+    pub fn new(key: impl Into<String>, body: &[u8]) -> Self {
+        IdempotencyRequest {
+            key: key.into(),
+            request_hash: IdempotencyKey::hash_request(body),
+        }
+    }
+}
+
+pub struct IdempotencyKey;
+
+impl IdempotencyKey {
+    pub fn hash_request(body: &[u8]) -> String {
+        hex::encode(Sha256::digest(body))
+    }
+
+    /// Claims `key` for `user_id` inside `tx`.
+    ///
+    /// A concurrent request with the same key blocks on the insert until the
+    /// first one commits or rolls back, so at most one of them executes.
+    #[instrument(skip_all, name = "IdempotencyKey::claim", fields(user_id = %user_id))]
+    pub async fn claim(
+        user_id: Uuid,
+        key: &str,
+        request_hash: &str,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<IdempotencyClaim, AppError> {
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            return Err(AppError::ValidationError(format!(
+                "Idempotency-Key must be between 1 and {} characters",
+                MAX_KEY_LENGTH
+            )));
+        }
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (user_id, idempotency_key, request_hash)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(request_hash)
+        .execute(&mut **tx)
+        .await?;
+
+        if inserted.rows_affected() == 1 {
+            return Ok(IdempotencyClaim::New);
+        }
+
+        let (stored_hash, response) = sqlx::query_as::<_, (String, Option<serde_json::Value>)>(
+            "SELECT request_hash, response FROM idempotency_keys WHERE user_id = $1 AND idempotency_key = $2",
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if stored_hash != request_hash {
+            return Err(AppError::Conflict(
+                "Idempotency-Key was already used with a different request body".into(),
+            ));
+        }
+
+        response
+            .map(IdempotencyClaim::Replay)
+            .ok_or_else(|| AppError::Conflict("Request with this Idempotency-Key is in progress".into()))
+    }
+
+    #[instrument(skip_all, name = "IdempotencyKey::store_response", fields(user_id = %user_id))]
+    pub async fn store_response(
+        user_id: Uuid,
+        key: &str,
+        response: &serde_json::Value,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE idempotency_keys SET response = $1 WHERE user_id = $2 AND idempotency_key = $3",
+        )
+        .bind(response)
+        .bind(user_id)
+        .bind(key)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_request_is_stable() {
+        let body = br#"{"amount":1000,"transaction_type":"credit","description":null}"#;
+        assert_eq!(IdempotencyKey::hash_request(body), IdempotencyKey::hash_request(body));
+        assert_eq!(IdempotencyKey::hash_request(body).len(), 64);
+    }
+
+    #[test]
+    fn test_hash_request_differs_for_different_bodies() {
+        let a = IdempotencyKey::hash_request(br#"{"amount":1000}"#);
+        let b = IdempotencyKey::hash_request(br#"{"amount":1001}"#);
+        assert_ne!(a, b);
+    }
+}
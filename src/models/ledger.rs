@@ -0,0 +1,293 @@
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Internal counter-accounts that money flows in from and out to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemAccount {
+    CashIn,
+    CashOut,
+    Fees,
+    /// Counter-account for manual balance corrections made by staff.
+    Adjustments,
+}
+
+impl SystemAccount {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SystemAccount::CashIn => "cash_in",
+            SystemAccount::CashOut => "cash_out",
+            SystemAccount::Fees => "fees",
+            SystemAccount::Adjustments => "adjustments",
+        }
+    }
+}
+
+/// The ledger account a posting is made against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedgerAccount {
+    /// The ledger account backing a customer's `accounts` row.
+    Customer(Uuid),
+    System(SystemAccount),
+}
+
+/// One leg of a journal entry. Positive amounts credit the account,
+/// negative amounts debit it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostingLine {
+    pub account: LedgerAccount,
+    pub amount: i64,
+}
+
+impl PostingLine {
+    pub fn credit(account: LedgerAccount, amount: i64) -> Self {
+        PostingLine { account, amount }
+    }
+
+    pub fn debit(account: LedgerAccount, amount: i64) -> Self {
+        PostingLine {
+            account,
+            amount: -amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: Uuid,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct Posting {
+    pub id: Uuid,
+    pub journal_entry_id: Uuid,
+    pub ledger_account_id: Uuid,
+    pub amount: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An account whose `account_balances` projection disagrees with its postings.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct BalanceDiscrepancy {
+    pub account_id: Uuid,
+    pub balance: i64,
+    pub ledger_balance: i64,
+}
+
+pub struct Ledger;
+
+impl Ledger {
+    /// Checks that the lines form a valid double entry: at least two legs,
+    /// no zero amounts, and a total of zero.
+    pub fn validate(lines: &[PostingLine]) -> Result<(), AppError> {
+        if lines.len() < 2 {
+            return Err(AppError::ValidationError(
+                "A journal entry needs at least two postings".into(),
+            ));
+        }
+        if lines.iter().any(|line| line.amount == 0) {
+            return Err(AppError::ValidationError(
+                "Postings must have a non-zero amount".into(),
+            ));
+        }
+
+        let total = lines
+            .iter()
+            .try_fold(0i64, |total, line| total.checked_add(line.amount))
+            .ok_or_else(|| AppError::ValidationError("Posting amounts overflow".into()))?;
+        if total != 0 {
+            return Err(AppError::ValidationError(format!(
+                "Journal entry is unbalanced by {}",
+                total
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Writes a balanced journal entry inside `tx`. The `postings_balanced`
+    /// constraint trigger re-checks the balance at commit.
+    #[instrument(skip_all, name = "Ledger::post")]
+    pub async fn post(
+        description: Option<&str>,
+        lines: &[PostingLine],
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<JournalEntry, AppError> {
+        Self::validate(lines)?;
+
+        let entry = sqlx::query_as::<_, JournalEntry>(
+            "INSERT INTO journal_entries (description) VALUES ($1) RETURNING *",
+        )
+        .bind(description)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        for line in lines {
+            let inserted = match line.account {
+                LedgerAccount::Customer(account_id) => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO postings (journal_entry_id, ledger_account_id, amount)
+                        SELECT $1, id, $3 FROM ledger_accounts WHERE account_id = $2
+                        "#,
+                    )
+                    .bind(entry.id)
+                    .bind(account_id)
+                    .bind(line.amount)
+                    .execute(&mut **tx)
+                    .await?
+                }
+                LedgerAccount::System(system) => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO postings (journal_entry_id, ledger_account_id, amount)
+                        SELECT $1, id, $3 FROM ledger_accounts WHERE code = $2
+                        "#,
+                    )
+                    .bind(entry.id)
+                    .bind(system.code())
+                    .bind(line.amount)
+                    .execute(&mut **tx)
+                    .await?
+                }
+            };
+
+            if inserted.rows_affected() != 1 {
+                return Err(AppError::NotFound("Ledger account".into()));
+            }
+        }
+
+        Ok(entry)
+    }
+
+    /// Creates the ledger account that backs a newly opened customer account.
+    #[instrument(
+        skip_all,
+        name = "Ledger::open_customer_account",
+        fields(account_id = %account_id),
+    )]
+    pub(crate) async fn open_customer_account(
+        account_id: Uuid,
+        name: &str,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), AppError> {
+        sqlx::query("INSERT INTO ledger_accounts (kind, account_id, name) VALUES ('customer', $1, $2)")
+            .bind(account_id)
+            .bind(name)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sum of all postings against a customer account.
+    #[instrument(skip_all, name = "Ledger::customer_balance", fields(account_id = %account_id))]
+    pub async fn customer_balance(account_id: Uuid, pool: &sqlx::PgPool) -> Result<i64, AppError> {
+        let balance = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COALESCE(SUM(p.amount), 0)::BIGINT
+            FROM ledger_accounts la
+            LEFT JOIN postings p ON p.ledger_account_id = la.id
+            WHERE la.account_id = $1
+            "#,
+        )
+        .bind(account_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(balance)
+    }
+
+    #[instrument(skip_all, name = "Ledger::get_postings", fields(account_id = %account_id))]
+    pub async fn get_postings(
+        account_id: Uuid,
+        pool: &sqlx::PgPool,
+    ) -> Result<Vec<Posting>, AppError> {
+        let postings = sqlx::query_as::<_, Posting>(
+            r#"
+            SELECT p.* FROM postings p
+            JOIN ledger_accounts la ON la.id = p.ledger_account_id
+            WHERE la.account_id = $1
+            ORDER BY p.created_at, p.id
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(postings)
+    }
+
+    /// Lists every account whose stored balance differs from its ledger balance.
+    #[instrument(skip_all, name = "Ledger::find_discrepancies")]
+    pub async fn find_discrepancies(
+        pool: &sqlx::PgPool,
+    ) -> Result<Vec<BalanceDiscrepancy>, AppError> {
+        let discrepancies = sqlx::query_as::<_, BalanceDiscrepancy>(
+            r#"
+            SELECT b.account_id, b.balance, COALESCE(SUM(p.amount), 0)::BIGINT AS ledger_balance
+            FROM account_balances b
+            JOIN ledger_accounts la ON la.account_id = b.account_id
+            LEFT JOIN postings p ON p.ledger_account_id = la.id
+            GROUP BY b.account_id, b.balance
+            HAVING b.balance <> COALESCE(SUM(p.amount), 0)
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(discrepancies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balanced_entry_is_valid() {
+        let account = LedgerAccount::Customer(Uuid::new_v4());
+        let lines = [
+            PostingLine::credit(account, 500),
+            PostingLine::debit(LedgerAccount::System(SystemAccount::CashIn), 500),
+        ];
+        assert!(Ledger::validate(&lines).is_ok());
+    }
+
+    #[test]
+    fn test_unbalanced_entry_is_rejected() {
+        let lines = [
+            PostingLine::credit(LedgerAccount::Customer(Uuid::new_v4()), 500),
+            PostingLine::debit(LedgerAccount::System(SystemAccount::CashIn), 400),
+        ];
+        assert!(Ledger::validate(&lines).is_err());
+    }
+
+    #[test]
+    fn test_single_leg_and_zero_amounts_are_rejected() {
+        let account = LedgerAccount::Customer(Uuid::new_v4());
+        assert!(Ledger::validate(&[PostingLine::credit(account, 0)]).is_err());
+        assert!(
+            Ledger::validate(&[
+                PostingLine::credit(account, 0),
+                PostingLine::debit(LedgerAccount::System(SystemAccount::Fees), 0),
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_system_account_codes() {
+        assert_eq!(SystemAccount::CashIn.code(), "cash_in");
+        assert_eq!(SystemAccount::CashOut.code(), "cash_out");
+        assert_eq!(SystemAccount::Fees.code(), "fees");
+        assert_eq!(SystemAccount::Adjustments.code(), "adjustments");
+    }
+}
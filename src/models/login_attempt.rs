@@ -0,0 +1,327 @@
+use crate::auth::lockout::LoginPolicy;
+use crate::error::AppError;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Most attempts returned by a single audit query.
+pub const MAX_AUDIT_RESULTS: i64 = 100;
+
+/// One row of the login audit trail.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct LoginAttempt {
+    pub id: Uuid,
+    pub email: String,
+    pub user_id: Option<Uuid>,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub outcome: LoginOutcome,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "login_outcome", rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    MfaRequired,
+    /// The password was right but the second factor wasn't.
+    MfaFailed,
+    InvalidCredentials,
+    Locked,
+}
+
+impl LoginOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::MfaRequired => "mfa_required",
+            LoginOutcome::MfaFailed => "mfa_failed",
+            LoginOutcome::InvalidCredentials => "invalid_credentials",
+            LoginOutcome::Locked => "locked",
+        }
+    }
+}
+
+/// Who is trying to log in, as far as the server can tell.
+#[derive(Debug, Clone)]
+pub struct LoginSource {
+    /// The submitted email, normalized; it need not belong to a user.
+    pub email: String,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+}
+
+/// What a lockout applies to. Password reset requests are counted apart
+/// from logins, so asking for resets never locks anyone out of logging in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrottleScope {
+    Email,
+    Ip,
+    ResetEmail,
+    ResetIp,
+}
+
+impl ThrottleScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThrottleScope::Email => "email",
+            ThrottleScope::Ip => "ip",
+            ThrottleScope::ResetEmail => "reset_email",
+            ThrottleScope::ResetIp => "reset_ip",
+        }
+    }
+}
+
+impl LoginSource {
+    pub fn new(email: &str, ip_address: String, user_agent: Option<String>) -> Self {
+        LoginSource {
+            email: normalize_email(email),
+            ip_address,
+            user_agent,
+        }
+    }
+
+    fn keys(&self) -> [(ThrottleScope, &str); 2] {
+        [
+            (ThrottleScope::Email, self.email.as_str()),
+            (ThrottleScope::Ip, self.ip_address.as_str()),
+        ]
+    }
+
+    fn reset_keys(&self) -> [(ThrottleScope, &str); 2] {
+        [
+            (ThrottleScope::ResetEmail, self.email.as_str()),
+            (ThrottleScope::ResetIp, self.ip_address.as_str()),
+        ]
+    }
+}
+
+impl LoginAttempt {
+    #[instrument(skip_all, name = "LoginAttempt::record")]
+    pub async fn record(
+        source: &LoginSource,
+        user_id: Option<Uuid>,
+        outcome: LoginOutcome,
+        pool: &sqlx::PgPool,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO login_attempts (email, user_id, ip_address, user_agent, outcome)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(&source.email)
+        .bind(user_id)
+        .bind(&source.ip_address)
+        .bind(&source.user_agent)
+        .bind(outcome)
+        .execute(pool)
+        .await?;
+        metrics::record_login(outcome);
+
+        Ok(())
+    }
+
+    /// Recent attempts, newest first, optionally narrowed to an email and/or address.
+    #[instrument(skip_all, name = "LoginAttempt::search")]
+    pub async fn search(
+        email: Option<&str>,
+        ip_address: Option<&str>,
+        pool: &sqlx::PgPool,
+    ) -> Result<Vec<Self>, AppError> {
+        let attempts = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM login_attempts
+            WHERE ($1::TEXT IS NULL OR email = $1)
+              AND ($2::TEXT IS NULL OR ip_address = $2)
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(email.map(normalize_email))
+        .bind(ip_address)
+        .bind(MAX_AUDIT_RESULTS)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(attempts)
+    }
+}
+
+/// Failure counting and lockouts for logins.
+pub struct LoginThrottle;
+
+impl LoginThrottle {
+    /// The time until which the email or the address is locked out, if either is.
+    #[instrument(skip_all, name = "LoginThrottle::locked_until")]
+    pub async fn locked_until(
+        source: &LoginSource,
+        pool: &sqlx::PgPool,
+    ) -> Result<Option<DateTime<Utc>>, AppError> {
+        Self::locked_until_keys(source.keys(), pool).await
+    }
+
+    /// Counts a failed attempt against both the email and the address,
+    /// locking either out once it reaches its threshold.
+    #[instrument(skip_all, name = "LoginThrottle::record_failure")]
+    pub async fn record_failure(
+        source: &LoginSource,
+        policy: &LoginPolicy,
+        pool: &sqlx::PgPool,
+    ) -> Result<(), AppError> {
+        Self::count(source.keys(), policy, pool).await
+    }
+
+    /// Counts a password reset request against the email and the address,
+    /// with the same thresholds as failed logins. Returns the time until
+    /// which requests are refused instead, if they already are.
+    #[instrument(skip_all, name = "LoginThrottle::record_reset_request")]
+    pub async fn record_reset_request(
+        source: &LoginSource,
+        policy: &LoginPolicy,
+        pool: &sqlx::PgPool,
+    ) -> Result<Option<DateTime<Utc>>, AppError> {
+        if let Some(locked_until) = Self::locked_until_keys(source.reset_keys(), pool).await? {
+            return Ok(Some(locked_until));
+        }
+        Self::count(source.reset_keys(), policy, pool).await?;
+
+        Ok(None)
+    }
+
+    async fn locked_until_keys(
+        keys: [(ThrottleScope, &str); 2],
+        pool: &sqlx::PgPool,
+    ) -> Result<Option<DateTime<Utc>>, AppError> {
+        let [(email_scope, email), (ip_scope, ip)] = keys;
+        let locked_until = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            r#"
+            SELECT MAX(locked_until) FROM login_throttles
+            WHERE ((scope = $1 AND key = $2) OR (scope = $3 AND key = $4))
+              AND locked_until > NOW()
+            "#,
+        )
+        .bind(email_scope.as_str())
+        .bind(email)
+        .bind(ip_scope.as_str())
+        .bind(ip)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(locked_until)
+    }
+
+    /// Adds one to each key's counter, locking a key out once it reaches its threshold.
+    async fn count(
+        keys: [(ThrottleScope, &str); 2],
+        policy: &LoginPolicy,
+        pool: &sqlx::PgPool,
+    ) -> Result<(), AppError> {
+        let mut tx = pool.begin().await?;
+
+        for (scope, key) in keys {
+            let threshold = match scope {
+                ThrottleScope::Email | ThrottleScope::ResetEmail => policy.max_failures_per_email,
+                ThrottleScope::Ip | ThrottleScope::ResetIp => policy.max_failures_per_ip,
+            };
+
+            let (failed_count, lockout_level) = sqlx::query_as::<_, (i32, i32)>(
+                r#"
+                INSERT INTO login_throttles (scope, key, failed_count)
+                VALUES ($1, $2, 1)
+                ON CONFLICT (scope, key) DO UPDATE SET
+                    failed_count = CASE WHEN login_throttles.updated_at < NOW() - $3
+                        THEN 1 ELSE login_throttles.failed_count + 1 END,
+                    lockout_level = CASE WHEN login_throttles.updated_at < NOW() - $3
+                        THEN 0 ELSE login_throttles.lockout_level END,
+                    updated_at = NOW()
+                RETURNING failed_count, lockout_level
+                "#,
+            )
+            .bind(scope.as_str())
+            .bind(key)
+            .bind(policy.failure_window())
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if failed_count >= threshold {
+                sqlx::query(
+                    r#"
+                    UPDATE login_throttles
+                    SET failed_count = 0, lockout_level = lockout_level + 1, locked_until = $3
+                    WHERE scope = $1 AND key = $2
+                    "#,
+                )
+                .bind(scope.as_str())
+                .bind(key)
+                .bind(Utc::now() + policy.lockout_duration(lockout_level))
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Forgets past failures for an email after a successful login. With 2FA
+    /// on, that is once the second factor has been accepted.
+    #[instrument(skip_all, name = "LoginThrottle::record_success")]
+    pub async fn record_success(source: &LoginSource, pool: &sqlx::PgPool) -> Result<(), AppError> {
+        Self::clear(ThrottleScope::Email, &source.email, pool).await?;
+        Ok(())
+    }
+
+    /// Lifts any lockout on `key`. Returns whether there was anything to clear.
+    #[instrument(skip_all, name = "LoginThrottle::clear")]
+    pub async fn clear(
+        scope: ThrottleScope,
+        key: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<bool, AppError> {
+        let key = match scope {
+            ThrottleScope::Email | ThrottleScope::ResetEmail => normalize_email(key),
+            ThrottleScope::Ip | ThrottleScope::ResetIp => key.to_owned(),
+        };
+        let cleared = sqlx::query("DELETE FROM login_throttles WHERE scope = $1 AND key = $2")
+            .bind(scope.as_str())
+            .bind(key)
+            .execute(pool)
+            .await?;
+
+        Ok(cleared.rows_affected() > 0)
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_normalizes_email() {
+        let source = LoginSource::new("  Alice@Example.COM ", "127.0.0.1".into(), None);
+        assert_eq!(source.email, "alice@example.com");
+        assert_eq!(source.keys()[0], (ThrottleScope::Email, "alice@example.com"));
+        assert_eq!(source.keys()[1], (ThrottleScope::Ip, "127.0.0.1"));
+    }
+
+    #[test]
+    fn test_login_outcome_serialization() {
+        assert_eq!(
+            serde_json::to_string(&LoginOutcome::InvalidCredentials).unwrap(),
+            "\"invalid_credentials\""
+        );
+        assert_eq!(serde_json::to_string(&LoginOutcome::MfaRequired).unwrap(), "\"mfa_required\"");
+        assert_eq!(LoginOutcome::MfaFailed.as_str(), "mfa_failed");
+    }
+}
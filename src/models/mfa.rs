@@ -0,0 +1,397 @@
+use crate::auth::scopes::Scope;
+use crate::auth::tokens::{generate_opaque_token, hash_opaque_token};
+use crate::auth::totp;
+use crate::error::AppError;
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Recovery codes handed out when 2FA is enabled.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// How long a login has to complete its second factor.
+pub const MFA_CHALLENGE_TTL_SECONDS: i64 = 5 * 60;
+/// Wrong codes tolerated per challenge before the login must start over.
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct TotpCredential {
+    pub user_id: Uuid,
+    #[serde(skip_serializing)] // Only ever shown once, at enrollment
+    pub secret: String,
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub last_used_step: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a user needs to add the account to an authenticator app.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// A second factor submitted by the user: a TOTP code or a recovery code.
+#[derive(Debug, Clone, Copy)]
+pub enum SecondFactor<'a> {
+    Totp(&'a str),
+    RecoveryCode(&'a str),
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct ChallengeRow {
+    id: Uuid,
+    user_id: Uuid,
+    attempts: i32,
+    expires_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+    scopes: Option<Vec<Scope>>,
+}
+
+impl TotpCredential {
+    /// Starts (or restarts) enrollment with a fresh secret. The secret does
+    /// not guard logins until [`TotpCredential::confirm`] succeeds.
+    #[instrument(skip_all, name = "TotpCredential::enroll", fields(user_id = %user_id))]
+    pub async fn enroll(
+        user_id: Uuid,
+        account_name: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<TotpEnrollment, AppError> {
+        let secret = totp::generate_secret();
+
+        let enrolled = sqlx::query(
+            r#"
+            INSERT INTO totp_credentials (user_id, secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_used_step = 0, created_at = NOW()
+            WHERE totp_credentials.enabled_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(&secret)
+        .execute(pool)
+        .await?;
+
+        if enrolled.rows_affected() == 0 {
+            return Err(AppError::Conflict(
+                "Two-factor authentication is already enabled".into(),
+            ));
+        }
+
+        Ok(TotpEnrollment {
+            otpauth_uri: totp::otpauth_uri(&secret, account_name),
+            secret,
+        })
+    }
+
+    /// Enables 2FA once the user proves their app produces valid codes.
+    /// Returns the recovery codes, which are never shown again.
+    #[instrument(skip_all, name = "TotpCredential::confirm", fields(user_id = %user_id))]
+    pub async fn confirm(
+        user_id: Uuid,
+        code: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<Vec<String>, AppError> {
+        let mut tx = pool.begin().await?;
+
+        let credential = Self::lock(user_id, &mut tx)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Pending two-factor enrollment".into()))?;
+        if credential.enabled_at.is_some() {
+            return Err(AppError::Conflict(
+                "Two-factor authentication is already enabled".into(),
+            ));
+        }
+        if !credential.accept_totp(code, &mut tx).await? {
+            return Err(AppError::ValidationError("Invalid verification code".into()));
+        }
+
+        sqlx::query("UPDATE totp_credentials SET enabled_at = NOW() WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        let recovery_codes = Self::replace_recovery_codes(user_id, &mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(recovery_codes)
+    }
+
+    /// Turns 2FA off. Requires a current code so a stolen access token alone
+    /// cannot remove the second factor.
+    #[instrument(skip_all, name = "TotpCredential::disable", fields(user_id = %user_id))]
+    pub async fn disable(
+        user_id: Uuid,
+        factor: SecondFactor<'_>,
+        pool: &sqlx::PgPool,
+    ) -> Result<(), AppError> {
+        let mut tx = pool.begin().await?;
+
+        let credential = Self::lock(user_id, &mut tx)
+            .await?
+            .filter(|c| c.enabled_at.is_some())
+            .ok_or_else(|| AppError::NotFound("Two-factor authentication".into()))?;
+        if !credential.accept(factor, &mut tx).await? {
+            return Err(AppError::Unauthorized("Invalid verification code".into()));
+        }
+
+        sqlx::query("DELETE FROM totp_credentials WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all, name = "TotpCredential::is_enabled", fields(user_id = %user_id))]
+    pub async fn is_enabled(user_id: Uuid, pool: &sqlx::PgPool) -> Result<bool, AppError> {
+        let enabled = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM totp_credentials WHERE user_id = $1 AND enabled_at IS NOT NULL)",
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(enabled)
+    }
+
+    async fn lock(
+        user_id: Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Option<Self>, AppError> {
+        let credential = sqlx::query_as::<_, Self>(
+            "SELECT * FROM totp_credentials WHERE user_id = $1 FOR UPDATE",
+        )
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn accept(
+        &self,
+        factor: SecondFactor<'_>,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<bool, AppError> {
+        match factor {
+            SecondFactor::Totp(code) => self.accept_totp(code, tx).await,
+            SecondFactor::RecoveryCode(code) => {
+                Self::consume_recovery_code(self.user_id, code, tx).await
+            }
+        }
+    }
+
+    /// Accepts a code at most once: its time step must be newer than the last
+    /// one used.
+    async fn accept_totp(
+        &self,
+        code: &str,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<bool, AppError> {
+        let step = match totp::verify(&self.secret, code, Utc::now().timestamp()) {
+            Some(step) if step > self.last_used_step => step,
+            _ => return Ok(false),
+        };
+
+        sqlx::query("UPDATE totp_credentials SET last_used_step = $1 WHERE user_id = $2")
+            .bind(step)
+            .bind(self.user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(true)
+    }
+
+    async fn consume_recovery_code(
+        user_id: Uuid,
+        code: &str,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<bool, AppError> {
+        let consumed = sqlx::query(
+            r#"
+            UPDATE recovery_codes SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(hash_opaque_token(&normalize_recovery_code(code)))
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(consumed.rows_affected() == 1)
+    }
+
+    async fn replace_recovery_codes(
+        user_id: Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<String>, AppError> {
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+        for code in &codes {
+            sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+                .bind(user_id)
+                .bind(hash_opaque_token(&normalize_recovery_code(code)))
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        Ok(codes)
+    }
+}
+
+/// The pending second step of a login for a user with 2FA enabled.
+pub struct MfaChallenge;
+
+impl MfaChallenge {
+    /// Issues a short-lived challenge token for `user_id`. It grants nothing
+    /// by itself; it can only be redeemed together with a second factor.
+    /// `scopes` are those requested at login, kept for the session.
+    #[instrument(skip_all, name = "MfaChallenge::issue", fields(user_id = %user_id))]
+    pub async fn issue(
+        user_id: Uuid,
+        scopes: Option<&[Scope]>,
+        pool: &sqlx::PgPool,
+    ) -> Result<String, AppError> {
+        let token = generate_opaque_token();
+
+        sqlx::query(
+            r#"
+            INSERT INTO mfa_challenges (user_id, token_hash, expires_at, scopes)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(user_id)
+        .bind(hash_opaque_token(&token))
+        .bind(Utc::now() + Duration::seconds(MFA_CHALLENGE_TTL_SECONDS))
+        .bind(scopes.map(Scope::normalize))
+        .execute(pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    /// The user a challenge belongs to, while it can still be redeemed. Lets
+    /// the caller check lockouts before a code is tried.
+    #[instrument(skip_all, name = "MfaChallenge::pending_user")]
+    pub async fn pending_user(token: &str, pool: &sqlx::PgPool) -> Result<Uuid, AppError> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT user_id FROM mfa_challenges
+            WHERE token_hash = $1 AND consumed_at IS NULL AND expires_at > NOW()
+              AND attempts < $2
+            "#,
+        )
+        .bind(hash_opaque_token(token))
+        .bind(MAX_CHALLENGE_ATTEMPTS)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired MFA challenge".into()))
+    }
+
+    /// Completes a login. Returns the user id and the requested scopes when
+    /// the factor is valid; the challenge is then spent. Wrong codes count
+    /// against the challenge.
+    #[instrument(skip_all, name = "MfaChallenge::redeem")]
+    pub async fn redeem(
+        token: &str,
+        factor: SecondFactor<'_>,
+        pool: &sqlx::PgPool,
+    ) -> Result<(Uuid, Option<Vec<Scope>>), AppError> {
+        let invalid = || AppError::Unauthorized("Invalid or expired MFA challenge".into());
+        let mut tx = pool.begin().await?;
+
+        let challenge = sqlx::query_as::<_, ChallengeRow>(
+            r#"
+            SELECT id, user_id, attempts, expires_at, consumed_at, scopes FROM mfa_challenges
+            WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(hash_opaque_token(token))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(invalid)?;
+
+        if challenge.consumed_at.is_some()
+            || challenge.expires_at <= Utc::now()
+            || challenge.attempts >= MAX_CHALLENGE_ATTEMPTS
+        {
+            return Err(invalid());
+        }
+
+        let accepted = match TotpCredential::lock(challenge.user_id, &mut tx).await? {
+            Some(credential) if credential.enabled_at.is_some() => {
+                credential.accept(factor, &mut tx).await?
+            }
+            _ => false,
+        };
+
+        if !accepted {
+            sqlx::query("UPDATE mfa_challenges SET attempts = attempts + 1 WHERE id = $1")
+                .bind(challenge.id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Err(AppError::Unauthorized("Invalid verification code".into()));
+        }
+
+        sqlx::query("UPDATE mfa_challenges SET consumed_at = NOW() WHERE id = $1")
+            .bind(challenge.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok((challenge.user_id, challenge.scopes))
+    }
+}
+
+/// Recovery codes look like `3f9a1-c07be`: ten hex characters, grouped for
+/// readability.
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Users may type recovery codes with or without the dash, in any case.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_code_format() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert_ne!(code, generate_recovery_code());
+    }
+
+    #[test]
+    fn test_recovery_code_normalization() {
+        assert_eq!(normalize_recovery_code("3F9A1-C07BE"), "3f9a1c07be");
+        assert_eq!(normalize_recovery_code(" 3f9a1c07be "), "3f9a1c07be");
+    }
+}
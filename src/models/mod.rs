@@ -1,7 +1,36 @@
+pub mod account;
 pub mod account_balance;
+pub mod adjustment;
+pub mod api_key;
+pub mod email_verification;
+pub mod hold;
+pub mod idempotency_key;
+pub mod ledger;
+pub mod login_attempt;
+pub mod mfa;
+pub mod password_reset;
+pub mod reversal;
+pub mod session;
 pub mod transaction;
+pub mod transfer;
 pub mod user;
 
-pub use account_balance::AccountBalance;
-pub use transaction::{Transaction, TransactionType};
-pub use user::User;
+pub use account::{Account, AccountStatus, AccountType};
+pub use account_balance::{AccountBalance, BalanceVerification};
+pub use adjustment::{Adjustment, AdjustmentRecord};
+pub use api_key::{ApiKey, CreatedApiKey};
+pub use email_verification::EmailVerification;
+pub use hold::{Capture, Hold, HoldStatus};
+pub use idempotency_key::{IdempotencyClaim, IdempotencyKey, IdempotencyRequest};
+pub use ledger::{JournalEntry, Ledger, LedgerAccount, Posting, PostingLine, SystemAccount};
+pub use login_attempt::{LoginAttempt, LoginOutcome, LoginSource, LoginThrottle, ThrottleScope};
+pub use mfa::{MfaChallenge, SecondFactor, TotpCredential, TotpEnrollment};
+pub use password_reset::PasswordReset;
+pub use reversal::{Reversal, StaffReversalRecord};
+pub use session::{Session, SessionSummary};
+pub use transaction::{
+    ReversalStatus, Transaction, TransactionCursor, TransactionFilter, TransactionPage,
+    TransactionType,
+};
+pub use transfer::Transfer;
+pub use user::{Role, User};
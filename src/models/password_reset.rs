@@ -0,0 +1,106 @@
+use crate::auth::tokens::{generate_opaque_token, hash_opaque_token};
+use crate::error::AppError;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{FromRow, Postgres, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::{Session, User};
+
+/// How long a password reset token stays valid.
+pub const PASSWORD_RESET_TTL_MINUTES: i64 = 30;
+
+#[derive(Debug, Clone, FromRow)]
+struct ResetTokenRow {
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+}
+
+pub struct PasswordReset;
+
+impl PasswordReset {
+    /// Issues a reset token for the user with `email`, withdrawing earlier
+    /// unused ones so only the latest message works. Returns `None` for
+    /// unknown emails so callers can respond identically either way.
+    #[instrument(skip_all, name = "PasswordReset::request")]
+    pub async fn request(
+        email: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<Option<(User, String)>, AppError> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
+            .bind(email.trim())
+            .fetch_optional(pool)
+            .await?;
+        let Some(user) = user else {
+            return Ok(None);
+        };
+
+        let token = generate_opaque_token();
+        let mut tx = pool.begin().await?;
+        Self::revoke_all(user.id, &mut tx).await?;
+        sqlx::query(
+            "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(user.id)
+        .bind(hash_opaque_token(&token))
+        .bind(Utc::now() + Duration::minutes(PASSWORD_RESET_TTL_MINUTES))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some((user, token)))
+    }
+
+    /// Sets a new password using a reset token. The token, and any other
+    /// outstanding tokens for the user, are spent, and all sessions revoked.
+    #[instrument(skip_all, name = "PasswordReset::complete")]
+    pub async fn complete(
+        token: &str,
+        new_password: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<(), AppError> {
+        let invalid = || AppError::ValidationError("Invalid or expired reset token".into());
+        let mut tx = pool.begin().await?;
+
+        let reset = sqlx::query_as::<_, ResetTokenRow>(
+            r#"
+            SELECT user_id, expires_at, used_at FROM password_reset_tokens
+            WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(hash_opaque_token(token))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(invalid)?;
+
+        if reset.used_at.is_some() || reset.expires_at <= Utc::now() {
+            return Err(invalid());
+        }
+
+        User::set_password(reset.user_id, new_password, &mut tx).await?;
+        Self::revoke_all(reset.user_id, &mut tx).await?;
+        Session::revoke_all(reset.user_id, None, &mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Spends every unused reset token of the user, inside an existing transaction.
+    #[instrument(skip_all, name = "PasswordReset::revoke_all", fields(user_id = %user_id))]
+    pub(crate) async fn revoke_all(
+        user_id: Uuid,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,485 @@
+use crate::error::AppError;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction as DbTransaction};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::ledger::{Ledger, LedgerAccount, PostingLine, SystemAccount};
+use super::transaction::NewTransaction;
+use super::{AccountBalance, Adjustment, ReversalStatus, Transaction, TransactionType, User};
+
+/// Audit record of a reversal made by staff.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct StaffReversalRecord {
+    pub id: Uuid,
+    /// The compensating transaction.
+    pub transaction_id: Uuid,
+    pub admin_id: Uuid,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The result of reversing (fully or partially) an earlier transaction.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Reversal {
+    /// The original transaction, with its updated reversal status.
+    pub original: Transaction,
+    /// The compensating transaction on the caller's account.
+    pub reversal: Transaction,
+    /// For refunded transfers, the compensating credit back to the sender.
+    pub counterpart: Option<Transaction>,
+    /// Who reversed it and why, for reversals made by staff.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staff_record: Option<StaffReversalRecord>,
+}
+
+/// Where a transaction's money came from or went to, which decides who may
+/// reverse it and which account the reversal is balanced against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Origin {
+    Cash,
+    Transfer(Uuid),
+    HoldCapture,
+    Adjustment,
+}
+
+impl Reversal {
+    /// Reverses `amount` of one of the user's own transactions (the whole
+    /// remaining amount when `None`). Reversals are capped at the original
+    /// amount, so the same money can never be reversed twice.
+    ///
+    /// Customers can only give back money they received: deposits are
+    /// returned, and a transfer is refunded by its recipient, moving the
+    /// money back to the sender's account. Debits, hold captures and
+    /// adjustments can only be reversed by staff, see [`Self::create_by_staff`].
+    /// Both take money out of the caller's account, so, like debits and
+    /// transfers, they need a verified email.
+    #[instrument(
+        skip_all,
+        name = "Reversal::create",
+        fields(user_id = %user_id, transaction_id = %transaction_id),
+    )]
+    pub async fn create(
+        user_id: Uuid,
+        transaction_id: Uuid,
+        amount: Option<i64>,
+        description: Option<String>,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        User::ensure_verified(user_id, pool).await?;
+        let mut tx = pool.begin().await?;
+
+        let original = sqlx::query_as::<_, Transaction>(
+            "SELECT * FROM transactions WHERE id = $1 AND user_id = $2 FOR UPDATE",
+        )
+        .bind(transaction_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Transaction".into()))?;
+
+        let origin = Self::origin(&original, &mut tx).await?;
+        Self::check_self_service(&original, origin)?;
+
+        let reversal = Self::reverse(original, origin, amount, description, &mut tx).await?;
+        tx.commit().await?;
+        reversal.record_metrics();
+
+        Ok(reversal)
+    }
+
+    /// Reverses any customer's transaction on behalf of staff. A reason is
+    /// mandatory and is kept alongside the id of the staff member, as for
+    /// adjustments.
+    #[instrument(
+        skip_all,
+        name = "Reversal::create_by_staff",
+        fields(admin_id = %admin_id, transaction_id = %transaction_id),
+    )]
+    pub async fn create_by_staff(
+        admin_id: Uuid,
+        transaction_id: Uuid,
+        amount: Option<i64>,
+        reason: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        let reason = Adjustment::validate_reason(reason)?;
+        let mut tx = pool.begin().await?;
+
+        let original =
+            sqlx::query_as::<_, Transaction>("SELECT * FROM transactions WHERE id = $1 FOR UPDATE")
+                .bind(transaction_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Transaction".into()))?;
+
+        Adjustment::check_not_own(admin_id, original.user_id)?;
+        let origin = Self::origin(&original, &mut tx).await?;
+        if matches!(origin, Origin::Transfer(_))
+            && original.transaction_type == TransactionType::Debit
+        {
+            return Err(AppError::ValidationError(
+                "Reverse the recipient's leg to refund a transfer".into(),
+            ));
+        }
+
+        // The reason stays in `staff_reversals`; the customer sees the default description.
+        let mut reversal = Self::reverse(original, origin, amount, None, &mut tx).await?;
+
+        let record = sqlx::query_as::<_, StaffReversalRecord>(
+            r#"
+            INSERT INTO staff_reversals (transaction_id, admin_id, reason)
+            VALUES ($1, $2, $3)
+            RETURNING *
+            "#,
+        )
+        .bind(reversal.reversal.id)
+        .bind(admin_id)
+        .bind(reason)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        reversal.record_metrics();
+        reversal.staff_record = Some(record);
+
+        Ok(reversal)
+    }
+
+    /// Finds out how `original` was posted.
+    async fn origin(
+        original: &Transaction,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<Origin, AppError> {
+        if let Some(transfer_id) = original.transfer_id {
+            return Ok(Origin::Transfer(transfer_id));
+        }
+        let (captured, adjusted) = sqlx::query_as::<_, (bool, bool)>(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM holds WHERE capture_transaction_id = $1),
+                   EXISTS (SELECT 1 FROM adjustments WHERE transaction_id = $1)
+            "#,
+        )
+        .bind(original.id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(if captured {
+            Origin::HoldCapture
+        } else if adjusted {
+            Origin::Adjustment
+        } else {
+            Origin::Cash
+        })
+    }
+
+    /// What customers may not reverse themselves: money that has already
+    /// left the bank, and postings made by the hold or adjustment flows.
+    fn check_self_service(original: &Transaction, origin: Origin) -> Result<(), AppError> {
+        match origin {
+            Origin::HoldCapture => Err(AppError::Forbidden(
+                "Hold captures can only be reversed by staff".into(),
+            )),
+            Origin::Adjustment => Err(AppError::Forbidden(
+                "Adjustments can only be reversed by staff".into(),
+            )),
+            Origin::Transfer(_) if original.transaction_type == TransactionType::Debit => Err(
+                AppError::ValidationError("Only the recipient can refund a transfer".into()),
+            ),
+            Origin::Cash if original.transaction_type == TransactionType::Debit => Err(
+                AppError::Forbidden("Debits can only be reversed by staff".into()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    async fn reverse(
+        original: Transaction,
+        origin: Origin,
+        amount: Option<i64>,
+        description: Option<String>,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        if original.reverses_transaction_id.is_some() {
+            return Err(AppError::ValidationError(
+                "A reversal cannot itself be reversed".into(),
+            ));
+        }
+
+        let amount = Self::reversible_amount(&original, amount)?;
+        let description = description.or_else(|| Some(format!("Reversal of {}", original.id)));
+
+        let (reversal, counterpart) = match origin {
+            Origin::Transfer(transfer_id) => {
+                Self::refund_transfer(&original, transfer_id, amount, description, tx).await?
+            }
+            _ => (
+                Self::reverse_to_system(&original, origin, amount, description, tx).await?,
+                None,
+            ),
+        };
+
+        let original = Self::mark_reversed(original.id, amount, tx).await?;
+
+        Ok(Reversal {
+            original,
+            reversal,
+            counterpart,
+            staff_record: None,
+        })
+    }
+
+    fn record_metrics(&self) {
+        for transaction in std::iter::once(&self.reversal).chain(&self.counterpart) {
+            metrics::record_transaction(transaction);
+        }
+    }
+
+    /// Validates the requested amount against what is left to reverse.
+    fn reversible_amount(original: &Transaction, requested: Option<i64>) -> Result<i64, AppError> {
+        let remaining = original.amount - original.reversed_amount;
+        let amount = requested.unwrap_or(remaining);
+
+        if remaining == 0 {
+            return Err(AppError::Conflict("Transaction is already fully reversed".into()));
+        }
+        if amount <= 0 {
+            return Err(AppError::ValidationError("Amount must be positive".into()));
+        }
+        if amount > remaining {
+            return Err(AppError::Conflict(format!(
+                "Only {} of this transaction remains reversible",
+                remaining
+            )));
+        }
+
+        Ok(amount)
+    }
+
+    fn compensating(original: &Transaction, amount: i64, description: Option<String>) -> NewTransaction {
+        NewTransaction {
+            user_id: original.user_id,
+            account_id: original.account_id,
+            amount,
+            transaction_type: match original.transaction_type {
+                TransactionType::Credit => TransactionType::Debit,
+                TransactionType::Debit => TransactionType::Credit,
+            },
+            description,
+            transfer_id: None,
+            reverses_transaction_id: Some(original.id),
+        }
+    }
+
+    /// Undoes a posting against the same system account it was balanced against.
+    async fn reverse_to_system(
+        original: &Transaction,
+        origin: Origin,
+        amount: i64,
+        description: Option<String>,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<Transaction, AppError> {
+        let reversal = Self::compensating(original, amount, description);
+        let counterparty = LedgerAccount::System(match (origin, original.transaction_type) {
+            (Origin::Adjustment, _) => SystemAccount::Adjustments,
+            (_, TransactionType::Credit) => SystemAccount::CashIn,
+            (_, TransactionType::Debit) => SystemAccount::CashOut,
+        });
+        let posting = reversal.posting();
+        let entry = Ledger::post(
+            reversal.description.as_deref(),
+            &[posting, PostingLine::credit(counterparty, -posting.amount)],
+            tx,
+        )
+        .await?;
+
+        Transaction::record(reversal, entry.id, tx).await
+    }
+
+    /// Moves money from the transfer's recipient back to its sender.
+    async fn refund_transfer(
+        original: &Transaction,
+        transfer_id: Uuid,
+        amount: i64,
+        description: Option<String>,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<(Transaction, Option<Transaction>), AppError> {
+        let sender_leg = sqlx::query_as::<_, Transaction>(
+            "SELECT * FROM transactions WHERE transfer_id = $1 AND id <> $2 FOR UPDATE",
+        )
+        .bind(transfer_id)
+        .bind(original.id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        AccountBalance::lock_for_update(&[original.account_id, sender_leg.account_id], tx).await?;
+
+        let refund_id = Uuid::new_v4();
+        let refund = NewTransaction {
+            transfer_id: Some(refund_id),
+            ..Self::compensating(original, amount, description.clone())
+        };
+        let credit_back = NewTransaction {
+            transfer_id: Some(refund_id),
+            ..Self::compensating(&sender_leg, amount, description)
+        };
+
+        let entry = Ledger::post(
+            refund.description.as_deref(),
+            &[refund.posting(), credit_back.posting()],
+            tx,
+        )
+        .await?;
+
+        let refund = Transaction::record(refund, entry.id, tx).await?;
+        let credit_back = Transaction::record(credit_back, entry.id, tx).await?;
+        Self::mark_reversed(sender_leg.id, amount, tx).await?;
+
+        Ok((refund, Some(credit_back)))
+    }
+
+    async fn mark_reversed(
+        transaction_id: Uuid,
+        amount: i64,
+        tx: &mut DbTransaction<'_, Postgres>,
+    ) -> Result<Transaction, AppError> {
+        let current = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT amount, reversed_amount FROM transactions WHERE id = $1",
+        )
+        .bind(transaction_id)
+        .fetch_one(&mut **tx)
+        .await?;
+        let reversed_amount = current.1 + amount;
+
+        let updated = sqlx::query_as::<_, Transaction>(
+            r#"
+            UPDATE transactions
+            SET reversed_amount = $1, reversal_status = $2
+            WHERE id = $3
+            RETURNING *
+            "#,
+        )
+        .bind(reversed_amount)
+        .bind(ReversalStatus::from_amounts(current.0, reversed_amount))
+        .bind(transaction_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(amount: i64, reversed_amount: i64) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            amount,
+            transaction_type: TransactionType::Credit,
+            description: None,
+            created_at: chrono::Utc::now(),
+            transfer_id: None,
+            journal_entry_id: Uuid::new_v4(),
+            reverses_transaction_id: None,
+            reversed_amount,
+            reversal_status: ReversalStatus::from_amounts(amount, reversed_amount),
+        }
+    }
+
+    #[test]
+    fn test_reversible_amount_defaults_to_remaining() {
+        assert_eq!(Reversal::reversible_amount(&transaction(1000, 300), None).unwrap(), 700);
+    }
+
+    #[test]
+    fn test_reversible_amount_rejects_over_reversal() {
+        assert!(Reversal::reversible_amount(&transaction(1000, 300), Some(701)).is_err());
+        assert!(Reversal::reversible_amount(&transaction(1000, 1000), None).is_err());
+        assert!(Reversal::reversible_amount(&transaction(1000, 0), Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_customers_can_reverse_credits() {
+        let credit = transaction(1000, 0);
+        assert!(Reversal::check_self_service(&credit, Origin::Cash).is_ok());
+        assert!(Reversal::check_self_service(&credit, Origin::Transfer(Uuid::new_v4())).is_ok());
+    }
+
+    #[test]
+    fn test_customers_cannot_reverse_debits() {
+        let debit = Transaction {
+            transaction_type: TransactionType::Debit,
+            ..transaction(1000, 0)
+        };
+        assert!(matches!(
+            Reversal::check_self_service(&debit, Origin::Cash),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            Reversal::check_self_service(&debit, Origin::Transfer(Uuid::new_v4())),
+            Err(AppError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_customers_cannot_reverse_captures_or_adjustments() {
+        let debit = Transaction {
+            transaction_type: TransactionType::Debit,
+            ..transaction(1000, 0)
+        };
+        for transaction in [debit, transaction(1000, 0)] {
+            assert!(matches!(
+                Reversal::check_self_service(&transaction, Origin::HoldCapture),
+                Err(AppError::Forbidden(_))
+            ));
+            assert!(matches!(
+                Reversal::check_self_service(&transaction, Origin::Adjustment),
+                Err(AppError::Forbidden(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compensating_transaction_flips_type() {
+        let original = transaction(1000, 0);
+        let reversal = Reversal::compensating(&original, 400, None);
+        assert_eq!(reversal.transaction_type, TransactionType::Debit);
+        assert_eq!(reversal.amount, 400);
+        assert_eq!(reversal.reverses_transaction_id, Some(original.id));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn test_unverified_customers_cannot_reverse(pool: sqlx::PgPool) {
+        let (user, balance) = User::register(
+            "unverified".into(),
+            "unverified@example.com".into(),
+            "correct-horse".into(),
+            &pool,
+        )
+        .await
+        .unwrap();
+        let deposit = Transaction::create(
+            user.id,
+            balance.account_id,
+            1000,
+            TransactionType::Credit,
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        let err = Reversal::create(user.id, deposit.id, None, None, &pool).await.unwrap_err();
+        assert!(matches!(err, AppError::Forbidden(_)));
+        let deposit = Transaction::get_by_id(deposit.id, user.id, &pool).await.unwrap();
+        assert_eq!(deposit.reversed_amount, 0);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::auth::scopes::Scope;
+use crate::auth::tokens::{generate_opaque_token, hash_opaque_token};
+use crate::error::AppError;
+use crate::models::LoginSource;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// `last_active_at` is refreshed at most this often, to keep requests cheap.
+const LAST_ACTIVE_RESOLUTION_SECONDS: i64 = 60;
+
+/// A login session. Every refresh token issued for it belongs to the same
+/// family; revoking the session invalidates all of them and every access
+/// token carrying its id.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Scopes requested at login; `None` for full access.
+    pub scopes: Option<Vec<Scope>>,
+    /// Unset for sessions started before devices were recorded.
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub last_active_at: DateTime<Utc>,
+}
+
+/// A session as listed to its owner.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionSummary {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct RefreshTokenRow {
+    id: Uuid,
+    session_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// Starts a new session for a login from `source` and returns it with
+    /// its first refresh token. Its access tokens carry `scopes`, or every
+    /// scope when `None`.
+    #[instrument(skip_all, name = "Session::start", fields(user_id = %user_id))]
+    pub async fn start(
+        user_id: Uuid,
+        source: &LoginSource,
+        scopes: Option<&[Scope]>,
+        refresh_ttl: Duration,
+        pool: &sqlx::PgPool,
+    ) -> Result<(Self, String), AppError> {
+        let mut tx = pool.begin().await?;
+
+        let session = sqlx::query_as::<_, Self>(
+            r#"
+            INSERT INTO sessions (user_id, scopes, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(scopes.map(Scope::normalize))
+        .bind(&source.ip_address)
+        .bind(&source.user_agent)
+        .fetch_one(&mut *tx)
+        .await?;
+        let refresh_token = Self::issue_refresh_token(session.id, refresh_ttl, &mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok((session, refresh_token))
+    }
+
+    /// Exchanges a refresh token for a new one. Each refresh token is single
+    /// use: presenting one that was already rotated means it leaked, so the
+    /// whole session is revoked.
+    #[instrument(skip_all, name = "Session::rotate")]
+    pub async fn rotate(
+        refresh_token: &str,
+        refresh_ttl: Duration,
+        pool: &sqlx::PgPool,
+    ) -> Result<(Self, String), AppError> {
+        let invalid = || AppError::Unauthorized("Invalid refresh token".into());
+        let mut tx = pool.begin().await?;
+
+        let token = sqlx::query_as::<_, RefreshTokenRow>(
+            r#"
+            SELECT id, session_id, expires_at, used_at FROM refresh_tokens
+            WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(hash_opaque_token(refresh_token))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(invalid)?;
+
+        let session = sqlx::query_as::<_, Self>("SELECT * FROM sessions WHERE id = $1 FOR UPDATE")
+            .bind(token.session_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        if session.revoked_at.is_some() {
+            return Err(invalid());
+        }
+        if token.used_at.is_some() {
+            Self::revoke_in_tx(session.id, &mut tx).await?;
+            tx.commit().await?;
+            return Err(AppError::Unauthorized(
+                "Refresh token reuse detected; session revoked".into(),
+            ));
+        }
+        if token.expires_at <= Utc::now() {
+            return Err(invalid());
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE id = $1")
+            .bind(token.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE sessions SET last_active_at = NOW() WHERE id = $1")
+            .bind(session.id)
+            .execute(&mut *tx)
+            .await?;
+        let refresh_token = Self::issue_refresh_token(session.id, refresh_ttl, &mut tx).await?;
+
+        tx.commit().await?;
+
+        Ok((session, refresh_token))
+    }
+
+    /// The scopes granted to this session's access tokens.
+    pub fn granted_scopes(&self) -> Vec<Scope> {
+        self.scopes.clone().unwrap_or_else(Scope::all)
+    }
+
+    /// Whether access tokens issued for this session are still honoured.
+    /// If so, records that the session was just used.
+    #[instrument(skip_all, name = "Session::record_activity", fields(id = %id))]
+    pub async fn record_activity(id: Uuid, pool: &sqlx::PgPool) -> Result<bool, AppError> {
+        let last_active_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+            "SELECT last_active_at FROM sessions WHERE id = $1 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        let Some(last_active_at) = last_active_at else {
+            return Ok(false);
+        };
+
+        if last_active_at < Utc::now() - Duration::seconds(LAST_ACTIVE_RESOLUTION_SECONDS) {
+            sqlx::query("UPDATE sessions SET last_active_at = NOW() WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+
+        Ok(true)
+    }
+
+    /// The user's sessions that can still be used or refreshed, most
+    /// recently active first.
+    #[instrument(skip_all, name = "Session::list_active", fields(user_id = %user_id))]
+    pub async fn list_active(user_id: Uuid, pool: &sqlx::PgPool) -> Result<Vec<Self>, AppError> {
+        let sessions = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM sessions s
+            WHERE s.user_id = $1 AND s.revoked_at IS NULL
+              AND EXISTS (
+                  SELECT 1 FROM refresh_tokens r
+                  WHERE r.session_id = s.id AND r.used_at IS NULL AND r.expires_at > NOW()
+              )
+            ORDER BY s.last_active_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    #[instrument(skip_all, name = "Session::revoke", fields(id = %id, user_id = %user_id))]
+    pub async fn revoke(id: Uuid, user_id: Uuid, pool: &sqlx::PgPool) -> Result<(), AppError> {
+        let revoked = sqlx::query(
+            "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+        if revoked.rows_affected() == 0 {
+            return Err(AppError::NotFound("Session".into()));
+        }
+
+        Ok(())
+    }
+
+    /// Revokes every active session of a user, except `keep` if given.
+    #[instrument(skip_all, name = "Session::revoke_all", fields(user_id = %user_id))]
+    pub(crate) async fn revoke_all(
+        user_id: Uuid,
+        keep: Option<Uuid>,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<u64, AppError> {
+        let revoked = sqlx::query(
+            r#"
+            UPDATE sessions SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL AND ($2::UUID IS NULL OR id <> $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(keep)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(revoked.rows_affected())
+    }
+
+    async fn revoke_in_tx(id: Uuid, tx: &mut Transaction<'_, Postgres>) -> Result<(), AppError> {
+        sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn issue_refresh_token(
+        session_id: Uuid,
+        ttl: Duration,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<String, AppError> {
+        let token = generate_opaque_token();
+
+        sqlx::query(
+            "INSERT INTO refresh_tokens (session_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(session_id)
+        .bind(hash_opaque_token(&token))
+        .bind(Utc::now() + ttl)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_flags_the_current_session() {
+        let now = Utc::now();
+        let session = Session {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            created_at: now,
+            revoked_at: None,
+            scopes: Some(vec![Scope::BalanceRead]),
+            ip_address: Some("203.0.113.7".into()),
+            user_agent: Some("BankApp/3.2 (iPhone)".into()),
+            last_active_at: now,
+        };
+        assert_eq!(session.granted_scopes(), vec![Scope::BalanceRead]);
+
+        let listed = serde_json::to_value(SessionSummary {
+            session,
+            current: true,
+        })
+        .unwrap();
+        assert_eq!(listed["current"], true);
+        assert_eq!(listed["user_agent"], "BankApp/3.2 (iPhone)");
+        assert_eq!(listed["ip_address"], "203.0.113.7");
+    }
+}
@@ -1,22 +1,42 @@
 use crate::error::AppError;
+use crate::metrics;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, Postgres};
+use tracing::instrument;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-use super::AccountBalance;
+use super::ledger::{Ledger, LedgerAccount, PostingLine, SystemAccount};
+use super::{AccountBalance, IdempotencyClaim, IdempotencyKey, IdempotencyRequest, User};
 
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+/// Largest amount, in minor units, a single request may move.
+pub const MAX_AMOUNT: i64 = 1_000_000_000;
+/// Longest free-text description stored with a transaction, transfer or hold.
+pub const MAX_DESCRIPTION_LENGTH: u64 = 255;
+/// Default for `limits.default_page_size`: history entries per page when the
+/// caller doesn't say.
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Default for `limits.max_page_size`.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
 pub struct Transaction {
     pub id: Uuid,
     pub user_id: Uuid,
+    pub account_id: Uuid,
     pub amount: i64,
     pub transaction_type: TransactionType,
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub transfer_id: Option<Uuid>,
+    pub journal_entry_id: Uuid,
+    pub reverses_transaction_id: Option<Uuid>,
+    pub reversed_amount: i64,
+    pub reversal_status: ReversalStatus,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Copy, sqlx::Type)]
+#[derive(Debug, Clone, Serialize, Deserialize, Copy, sqlx::Type, ToSchema)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "transaction_type", rename_all = "lowercase")]
 
@@ -26,9 +46,124 @@ pub enum TransactionType {
     Credit,
 }
 
+impl TransactionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionType::Debit => "debit",
+            TransactionType::Credit => "credit",
+        }
+    }
+}
+
+/// How much of a transaction has been undone by later reversals.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "reversal_status", rename_all = "snake_case")]
+pub enum ReversalStatus {
+    None,
+    PartiallyReversed,
+    Reversed,
+}
+
+impl ReversalStatus {
+    pub fn from_amounts(amount: i64, reversed_amount: i64) -> Self {
+        if reversed_amount <= 0 {
+            ReversalStatus::None
+        } else if reversed_amount < amount {
+            ReversalStatus::PartiallyReversed
+        } else {
+            ReversalStatus::Reversed
+        }
+    }
+}
+
+/// The fields needed to record a new transaction row.
+#[derive(Debug, Clone)]
+pub(crate) struct NewTransaction {
+    pub user_id: Uuid,
+    pub account_id: Uuid,
+    pub amount: i64,
+    pub transaction_type: TransactionType,
+    pub description: Option<String>,
+    pub transfer_id: Option<Uuid>,
+    pub reverses_transaction_id: Option<Uuid>,
+}
+
+impl NewTransaction {
+    /// The posting this transaction makes against the customer's ledger account.
+    pub fn posting(&self) -> PostingLine {
+        let account = LedgerAccount::Customer(self.account_id);
+        match self.transaction_type {
+            TransactionType::Credit => PostingLine::credit(account, self.amount),
+            TransactionType::Debit => PostingLine::debit(account, self.amount),
+        }
+    }
+}
+
+/// Optional filters applied when listing a user's transaction history.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    pub account_id: Option<Uuid>,
+    pub transaction_type: Option<TransactionType>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    pub description: Option<String>,
+}
+
+/// Position in a user's history, ordered by `(created_at, id)` descending.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransactionCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl TransactionCursor {
+    /// Opaque string form handed to clients as `next_cursor`.
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}|{}", self.created_at.to_rfc3339(), self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::ValidationError("Invalid cursor".into());
+
+        let raw = hex::decode(cursor).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (created_at, id) = raw.split_once('|').ok_or_else(invalid)?;
+
+        Ok(TransactionCursor {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    pub next_cursor: Option<String>,
+}
+
+/// Escapes `LIKE` wildcards so a description filter matches literally.
+pub(super) fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
 impl Transaction {
+    #[instrument(
+        skip_all,
+        name = "Transaction::create",
+        fields(user_id = %user_id, account_id = %account_id),
+    )]
     pub async fn create(
         user_id: Uuid,
+        account_id: Uuid,
         amount: i64,
         transaction_type: TransactionType,
         description: Option<String>,
@@ -38,48 +173,250 @@ impl Transaction {
         if amount <= 0 {
             return Err(AppError::ValidationError("Amount must be positive".into()));
         }
+        if transaction_type == TransactionType::Debit {
+            User::ensure_verified(user_id, pool).await?;
+        }
 
         let mut tx = pool.begin().await?;
 
+        let transaction = Self::record_cash(
+            NewTransaction {
+                user_id,
+                account_id,
+                amount,
+                transaction_type,
+                description,
+                transfer_id: None,
+                reverses_transaction_id: None,
+            },
+            &mut tx,
+        )
+        .await?;
+
+        tx.commit().await?;
+        metrics::record_transaction(&transaction);
+
+        Ok(transaction)
+    }
+
+    /// Like [`Transaction::create`], but guarded by a per-user idempotency key.
+    /// Replaying a key returns the originally created transaction without
+    /// touching the account balance again.
+    #[instrument(
+        skip_all,
+        name = "Transaction::create_idempotent",
+        fields(user_id = %user_id, account_id = %account_id),
+    )]
+    pub async fn create_idempotent(
+        user_id: Uuid,
+        account_id: Uuid,
+        amount: i64,
+        transaction_type: TransactionType,
+        description: Option<String>,
+        idempotency: &IdempotencyRequest,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        if amount <= 0 {
+            return Err(AppError::ValidationError("Amount must be positive".into()));
+        }
+        if transaction_type == TransactionType::Debit {
+            User::ensure_verified(user_id, pool).await?;
+        }
+
+        let mut tx = pool.begin().await?;
+
+        match IdempotencyKey::claim(user_id, &idempotency.key, &idempotency.request_hash, &mut tx)
+            .await?
+        {
+            IdempotencyClaim::Replay(response) => {
+                tx.rollback().await?;
+                serde_json::from_value(response).map_err(|_| {
+                    AppError::Conflict("Stored response for Idempotency-Key is unreadable".into())
+                })
+            }
+            IdempotencyClaim::New => {
+                let transaction = Self::record_cash(
+                    NewTransaction {
+                        user_id,
+                        account_id,
+                        amount,
+                        transaction_type,
+                        description,
+                        transfer_id: None,
+                        reverses_transaction_id: None,
+                    },
+                    &mut tx,
+                )
+                .await?;
+                let response =
+                    serde_json::to_value(&transaction).expect("Transaction serializes to JSON");
+                IdempotencyKey::store_response(user_id, &idempotency.key, &response, &mut tx)
+                    .await?;
+                tx.commit().await?;
+                metrics::record_transaction(&transaction);
+
+                Ok(transaction)
+            }
+        }
+    }
+
+    /// Records a deposit or withdrawal, balanced against the cash-in or
+    /// cash-out system account.
+    #[instrument(skip_all, name = "Transaction::record_cash")]
+    pub(crate) async fn record_cash(
+        new: NewTransaction,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        let counterparty = LedgerAccount::System(match new.transaction_type {
+            TransactionType::Credit => SystemAccount::CashIn,
+            TransactionType::Debit => SystemAccount::CashOut,
+        });
+        let posting = new.posting();
+        let entry = Ledger::post(
+            new.description.as_deref(),
+            &[posting, PostingLine::credit(counterparty, -posting.amount)],
+            tx,
+        )
+        .await?;
+
+        Self::record(new, entry.id, tx).await
+    }
+
+    /// Inserts a transaction row for an already posted journal entry and
+    /// applies it to the account's balance projection.
+    #[instrument(
+        skip_all,
+        name = "Transaction::record",
+        fields(journal_entry_id = %journal_entry_id),
+    )]
+    pub(crate) async fn record(
+        new: NewTransaction,
+        journal_entry_id: Uuid,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+    ) -> Result<Self, AppError> {
+        let NewTransaction {
+            user_id,
+            account_id,
+            amount,
+            transaction_type,
+            description,
+            transfer_id,
+            reverses_transaction_id,
+        } = new;
+
+        let owned = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM accounts WHERE id = $1 AND user_id = $2)",
+        )
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if !owned {
+            return Err(AppError::NotFound("Account".into()));
+        }
+
         let transaction = sqlx::query_as::<_, Self>(
             r#"
-            INSERT INTO transactions (user_id, amount, transaction_type, description)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO transactions
+                (user_id, account_id, amount, transaction_type, description, transfer_id,
+                 journal_entry_id, reverses_transaction_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
             RETURNING *
             "#,
         )
         .bind(user_id)
+        .bind(account_id)
         .bind(amount)
         .bind(transaction_type)
         .bind(description)
-        .fetch_one(&mut *tx)
+        .bind(transfer_id)
+        .bind(journal_entry_id)
+        .bind(reverses_transaction_id)
+        .fetch_one(&mut **tx)
         .await?;
 
         match transaction_type {
             TransactionType::Credit => {
-                AccountBalance::credit(user_id, amount, &mut tx).await?;
+                AccountBalance::credit(account_id, amount, tx).await?;
             }
             TransactionType::Debit => {
-                AccountBalance::debit(user_id, amount, &mut tx).await?;
+                AccountBalance::debit(account_id, amount, tx).await?;
             }
         }
-        tx.commit().await?;
 
         Ok(transaction)
     }
 
-    pub async fn get_by_user(user_id: Uuid, pool: &sqlx::PgPool) -> Result<Vec<Self>, AppError> {
-        let transactions = sqlx::query_as::<_, Self>(
-            "
+    /// Fetches a single transaction, scoped to its owner so users can only
+    /// ever see their own transactions.
+    #[instrument(skip_all, name = "Transaction::get_by_id", fields(id = %id, user_id = %user_id))]
+    pub async fn get_by_id(id: Uuid, user_id: Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM transactions WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Transaction".into()))
+    }
+
+    /// Returns one page of a user's history, newest first, starting after `cursor`.
+    #[instrument(skip_all, name = "Transaction::get_by_user", fields(user_id = %user_id))]
+    pub async fn get_by_user(
+        user_id: Uuid,
+        filter: &TransactionFilter,
+        cursor: Option<TransactionCursor>,
+        limit: i64,
+        pool: &sqlx::PgPool,
+    ) -> Result<TransactionPage, AppError> {
+        // Fetch one extra row to learn whether another page exists.
+        let mut transactions = sqlx::query_as::<_, Self>(
+            r#"
             SELECT * FROM transactions
-            WHERE user_id = $1 ORDER BY created_at DESC
-            ",
+            WHERE user_id = $1
+              AND ($2::uuid IS NULL OR account_id = $2)
+              AND ($3::transaction_type IS NULL OR transaction_type = $3)
+              AND ($4::timestamptz IS NULL OR created_at >= $4)
+              AND ($5::timestamptz IS NULL OR created_at < $5)
+              AND ($6::bigint IS NULL OR amount >= $6)
+              AND ($7::bigint IS NULL OR amount <= $7)
+              AND ($8::text IS NULL OR description ILIKE '%' || $8 || '%')
+              AND ($9::timestamptz IS NULL OR (created_at, id) < ($9, $10))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $11
+            "#,
         )
         .bind(user_id)
+        .bind(filter.account_id)
+        .bind(filter.transaction_type)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.min_amount)
+        .bind(filter.max_amount)
+        .bind(filter.description.as_deref().map(escape_like))
+        .bind(cursor.map(|c| c.created_at))
+        .bind(cursor.map(|c| c.id))
+        .bind(limit + 1)
         .fetch_all(pool)
         .await?;
 
-        Ok(transactions)
+        let next_cursor = if transactions.len() as i64 > limit {
+            transactions.truncate(limit as usize);
+            transactions.last().map(|last| {
+                TransactionCursor {
+                    created_at: last.created_at,
+                    id: last.id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Ok(TransactionPage {
+            transactions,
+            next_cursor,
+        })
     }
 }
 
@@ -112,6 +449,7 @@ mod tests {
     fn test_transaction_struct_fields() {
         let id = Uuid::new_v4();
         let user_id = Uuid::new_v4();
+        let account_id = Uuid::new_v4();
         let amount = 1000;
         let transaction_type = TransactionType::Credit;
         let description = Some("Test".to_string());
@@ -120,17 +458,80 @@ mod tests {
         let tx = Transaction {
             id,
             user_id,
+            account_id,
             amount,
             transaction_type,
             description: description.clone(),
             created_at: now,
+            transfer_id: None,
+            journal_entry_id: Uuid::new_v4(),
+            reverses_transaction_id: None,
+            reversed_amount: 0,
+            reversal_status: ReversalStatus::None,
         };
 
         assert_eq!(tx.id, id);
         assert_eq!(tx.user_id, user_id);
+        assert_eq!(tx.account_id, account_id);
         assert_eq!(tx.amount, 1000);
         assert_eq!(tx.transaction_type, TransactionType::Credit);
         assert_eq!(tx.description, description);
         assert_eq!(tx.created_at, now);
+        assert_eq!(tx.transfer_id, None);
+        assert_eq!(tx.reversal_status, ReversalStatus::None);
+    }
+
+    #[test]
+    fn test_reversal_status_from_amounts() {
+        assert_eq!(ReversalStatus::from_amounts(1000, 0), ReversalStatus::None);
+        assert_eq!(
+            ReversalStatus::from_amounts(1000, 400),
+            ReversalStatus::PartiallyReversed
+        );
+        assert_eq!(ReversalStatus::from_amounts(1000, 1000), ReversalStatus::Reversed);
+    }
+
+    #[test]
+    fn test_new_transaction_posting_sign() {
+        let new = NewTransaction {
+            user_id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            amount: 250,
+            transaction_type: TransactionType::Debit,
+            description: None,
+            transfer_id: None,
+            reverses_transaction_id: None,
+        };
+        assert_eq!(new.posting().amount, -250);
+
+        let credit = NewTransaction {
+            transaction_type: TransactionType::Credit,
+            ..new
+        };
+        assert_eq!(credit.posting().amount, 250);
+        assert_eq!(credit.posting().account, LedgerAccount::Customer(credit.account_id));
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = TransactionCursor {
+            created_at: chrono::Utc::now(),
+            id: Uuid::new_v4(),
+        };
+
+        let decoded = TransactionCursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded, cursor);
+    }
+
+    #[test]
+    fn test_cursor_rejects_garbage() {
+        assert!(TransactionCursor::decode("not-a-cursor").is_err());
+        assert!(TransactionCursor::decode(&hex::encode("missing-separator")).is_err());
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("50%_off"), "50\\%\\_off");
+        assert_eq!(escape_like("rent"), "rent");
     }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use crate::error::AppError;
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::ledger::Ledger;
+use super::transaction::NewTransaction;
+use super::{Account, AccountBalance, Transaction, TransactionType, User};
+
+/// A peer-to-peer transfer: a linked debit/credit pair of transactions that
+/// share the same `transfer_id`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Transfer {
+    pub transfer_id: Uuid,
+    pub sender_id: Uuid,
+    pub recipient_id: Uuid,
+    pub from_account_id: Uuid,
+    pub to_account_id: Uuid,
+    pub amount: i64,
+    pub debit: Transaction,
+    pub credit: Transaction,
+}
+
+impl Transfer {
+    /// Moves `amount` from one of the sender's accounts into the recipient's
+    /// primary account.
+    #[instrument(
+        skip_all,
+        name = "Transfer::create",
+        fields(sender_id = %sender_id, from_account_id = %from_account_id),
+    )]
+    pub async fn create(
+        sender_id: Uuid,
+        from_account_id: Uuid,
+        recipient: &str,
+        amount: i64,
+        description: Option<String>,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        if amount <= 0 {
+            return Err(AppError::ValidationError("Amount must be positive".into()));
+        }
+        User::ensure_verified(sender_id, pool).await?;
+
+        let recipient = User::find_by_username_or_email(recipient, pool).await?;
+        if recipient.id == sender_id {
+            return Err(AppError::ValidationError(
+                "Cannot transfer to your own account".into(),
+            ));
+        }
+
+        let to_account = Account::get_primary(recipient.id, pool).await?;
+
+        let transfer_id = Uuid::new_v4();
+        let mut tx = pool.begin().await?;
+
+        // Take both row locks up front, in a fixed order, before touching either balance.
+        AccountBalance::lock_for_update(&[from_account_id, to_account.id], &mut tx).await?;
+
+        let debit = NewTransaction {
+            user_id: sender_id,
+            account_id: from_account_id,
+            amount,
+            transaction_type: TransactionType::Debit,
+            description: description.clone(),
+            transfer_id: Some(transfer_id),
+            reverses_transaction_id: None,
+        };
+        let credit = NewTransaction {
+            user_id: recipient.id,
+            account_id: to_account.id,
+            transaction_type: TransactionType::Credit,
+            ..debit.clone()
+        };
+
+        // Both legs share one journal entry: money moves directly between the two accounts.
+        let entry = Ledger::post(
+            description.as_deref(),
+            &[debit.posting(), credit.posting()],
+            &mut tx,
+        )
+        .await?;
+
+        let debit = Transaction::record(debit, entry.id, &mut tx).await?;
+        let credit = Transaction::record(credit, entry.id, &mut tx).await?;
+
+        tx.commit().await?;
+        metrics::record_transaction(&debit);
+        metrics::record_transaction(&credit);
+
+        Ok(Transfer {
+            transfer_id,
+            sender_id,
+            recipient_id: recipient.id,
+            from_account_id,
+            to_account_id: to_account.id,
+            amount,
+            debit,
+            credit,
+        })
+    }
+}
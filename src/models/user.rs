@@ -1,12 +1,18 @@
 use bcrypt::{DEFAULT_COST, hash, verify};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, Postgres, Transaction};
+use std::sync::LazyLock;
+use tracing::instrument;
+use utoipa::ToSchema;
 use uuid::Uuid;
+use validator::ValidateEmail;
 
-use super::AccountBalance;
+use super::transaction::escape_like;
+use super::{Account, AccountBalance, AccountType, PasswordReset, Session};
+use crate::error::AppError;
 
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ToSchema)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
@@ -15,47 +21,89 @@ pub struct User {
     pub password_hash: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub role: Role,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub pending_email: Option<String>, // Awaiting confirmation; `email` stays active until then
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+pub enum Role {
+    Customer,
+    Support,
+    Admin,
+    Auditor,
+}
+
+impl Role {
+    /// Back-office roles, allowed into the admin API.
+    pub fn is_staff(&self) -> bool {
+        !matches!(self, Role::Customer)
+    }
+}
+
+/// Compared against when the email is unknown, to keep timing uniform.
+static DUMMY_PASSWORD_HASH: LazyLock<String> =
+    LazyLock::new(|| hash("not-a-real-password", DEFAULT_COST).expect("bcrypt hash"));
+
+/// Usernames are 3 to 32 characters from [`USERNAME_SYMBOLS`], letters and digits.
+pub const MIN_USERNAME_LENGTH: usize = 3;
+pub const MAX_USERNAME_LENGTH: usize = 32;
+pub const USERNAME_SYMBOLS: &[char] = &['_', '.', '-'];
+
+/// Shortest password accepted for new credentials.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// bcrypt ignores everything after 72 bytes, so longer passwords are refused.
+pub const MAX_PASSWORD_BYTES: usize = 72;
+
+/// Longest email address accepted (RFC 5321 path limit).
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// Most users returned by a single staff search.
+pub const MAX_SEARCH_RESULTS: i64 = 50;
+
 impl User {
     // User registration
+    #[instrument(skip_all, name = "User::register")]
     pub async fn register(
         username: String,
         email: String,
         password: String,
         pool: &sqlx::PgPool,
-    ) -> Result<(Self,AccountBalance), anyhow::Error> {
+    ) -> Result<(Self,AccountBalance), AppError> {
+
+        validate_username(&username)?;
+        validate_password(&password)?;
+        let email = email.trim().to_owned();
+        validate_email(&email)?;
+
+        // The unique index covers current emails; pending changes are checked here.
+        if Self::email_claimed(&email, None, pool).await? {
+            return Err(AppError::Conflict("Username or email is already taken".into()));
+        }
 
         // Start a transaction
         let mut tx = pool.begin().await?;
 
         let hashed_password = hash(password, DEFAULT_COST)?;
-        let user = sqlx::query_as!(
-            Self,
+        let user = sqlx::query_as::<_, Self>(
             r#"
             INSERT INTO users (username, email, password_hash)
             VALUES ($1, $2, $3)
             RETURNING *
             "#,
-            username,
-            email,
-            hashed_password
         )
+        .bind(username)
+        .bind(email)
+        .bind(hashed_password)
         .fetch_one(&mut *tx)
-        .await?;  
+        .await
+        .map_err(already_taken)?;
 
-         // Create the account balance with initial 0 balance
-        let account_balance = sqlx::query_as!(
-            AccountBalance,
-            r#"
-            INSERT INTO account_balances (user_id, balance, last_updated)
-            VALUES ($1, 0, NOW())
-            RETURNING *
-            "#,
-            user.id
-        )
-        .fetch_one(&mut *tx)
-        .await?;
+        // Open a default checking account with initial 0 balance
+        let (_, account_balance) =
+            Account::open_in_tx(user.id, AccountType::Checking, None, &mut tx).await?;
 
         // Commit the transaction
         tx.commit().await?;
@@ -64,57 +112,291 @@ impl User {
     }
 
     // User authentication
+    #[instrument(skip_all, name = "User::authenticate")]
     pub async fn authenticate(
         email: String,
         password: String,
         pool: &sqlx::PgPool,
-    ) -> Result<Self, anyhow::Error> {
-        let user = sqlx::query_as!(Self, "SELECT * FROM users WHERE email = $1", email)
+    ) -> Result<Self, AppError> {
+        // Unknown emails and wrong passwords are indistinguishable to the caller.
+        let invalid = || AppError::Unauthorized("Invalid credentials".into());
+        // Trimmed like the lockout key in `LoginSource`.
+        let user = sqlx::query_as::<_, Self>("SELECT * FROM users WHERE email = $1")
+            .bind(email.trim())
             .fetch_optional(pool)
-            .await?
-            .ok_or(anyhow::anyhow!("User not found"))?;
+            .await?;
 
+        let Some(user) = user else {
+            // Spend the same bcrypt work as a real check so response times
+            // don't reveal which emails are registered.
+            let _ = verify(password, &DUMMY_PASSWORD_HASH);
+            return Err(invalid());
+        };
         if verify(password, &user.password_hash)? {
             Ok(user)
         } else {
-            Err(anyhow::anyhow!("Invalid credentials"))
+            Err(invalid())
         }
     }
 
-    pub async fn get_by_id(user_id: &Uuid, pool: &sqlx::PgPool) -> Result<Self, anyhow::Error> {
-        let user = sqlx::query_as!(Self, "SELECT * FROM users WHERE id = $1", user_id)
-            .fetch_one(pool)
+    /// The id of the user registered with `email`, for the login audit trail.
+    #[instrument(skip_all, name = "User::find_id_by_email")]
+    pub async fn find_id_by_email(
+        email: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<Option<Uuid>, AppError> {
+        let user_id = sqlx::query_scalar::<_, Uuid>("SELECT id FROM users WHERE email = $1")
+            .bind(email.trim())
+            .fetch_optional(pool)
             .await?;
-        Ok(user)
+        Ok(user_id)
+    }
+
+    /// Looks up a user by either their username or their email address.
+    /// An identifier containing `@` is matched against emails first; usernames
+    /// from before the character rules may contain `@` too, and only match
+    /// when no user has that email.
+    #[instrument(skip_all, name = "User::find_by_username_or_email")]
+    pub async fn find_by_username_or_email(
+        identifier: &str,
+        pool: &sqlx::PgPool,
+    ) -> Result<Self, AppError> {
+        let query = if identifier.contains('@') {
+            r#"
+            SELECT * FROM users
+            WHERE email = $1 OR username = $1
+            ORDER BY email = $1 DESC
+            LIMIT 1
+            "#
+        } else {
+            "SELECT * FROM users WHERE username = $1"
+        };
+        sqlx::query_as::<_, Self>(query)
+            .bind(identifier)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User".into()))
+    }
+
+    #[instrument(skip_all, name = "User::get_by_id", fields(user_id = %user_id))]
+    pub async fn get_by_id(user_id: &Uuid, pool: &sqlx::PgPool) -> Result<Self, AppError> {
+        sqlx::query_as::<_, Self>("SELECT * FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User".into()))
+    }
+
+    /// Staff lookup: users whose username or email contains `term`
+    /// (case-insensitive), newest first.
+    #[instrument(skip_all, name = "User::search")]
+    pub async fn search(term: Option<&str>, pool: &sqlx::PgPool) -> Result<Vec<Self>, AppError> {
+        let users = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM users
+            WHERE $1::TEXT IS NULL OR username ILIKE $1 OR email ILIKE $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(term.map(|t| format!("%{}%", escape_like(t))))
+        .bind(MAX_SEARCH_RESULTS)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    pub fn is_email_verified(&self) -> bool {
+        self.email_verified_at.is_some()
+    }
+
+    /// Rejects users who have not confirmed their email address. Required
+    /// before any money can leave their accounts.
+    #[instrument(skip_all, name = "User::ensure_verified", fields(user_id = %user_id))]
+    pub async fn ensure_verified(user_id: Uuid, pool: &sqlx::PgPool) -> Result<(), AppError> {
+        let verified = sqlx::query_scalar::<_, bool>(
+            "SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User".into()))?;
+
+        if !verified {
+            return Err(AppError::Forbidden(
+                "Verify your email address before making debits or transfers".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Changes the password after checking the current one. Every other
+    /// session is revoked; `keep_session` (the caller's) stays signed in.
+    /// Outstanding reset tokens are spent, so an older reset email can't
+    /// undo the change.
+    #[instrument(skip_all, name = "User::change_password")]
+    pub async fn change_password(
+        &self,
+        current_password: &str,
+        new_password: &str,
+        keep_session: Option<Uuid>,
+        pool: &sqlx::PgPool,
+    ) -> Result<(), AppError> {
+        if !verify(current_password, &self.password_hash)? {
+            return Err(AppError::Unauthorized("Current password is incorrect".into()));
+        }
+
+        let mut tx = pool.begin().await?;
+        Self::set_password(self.id, new_password, &mut tx).await?;
+        PasswordReset::revoke_all(self.id, &mut tx).await?;
+        Session::revoke_all(self.id, keep_session, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Stores a new password hash inside an existing transaction.
+    #[instrument(skip_all, name = "User::set_password", fields(user_id = %user_id))]
+    pub(crate) async fn set_password(
+        user_id: Uuid,
+        password: &str,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), AppError> {
+        validate_password(password)?;
+        let hashed_password = hash(password, DEFAULT_COST)?;
+
+        sqlx::query("UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2")
+            .bind(hashed_password)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
     }
 
     // Update profile
+    #[instrument(skip_all, name = "User::update_profile")]
     pub async fn update_profile(
         &self,
         new_username: Option<String>,
         new_email: Option<String>,
         pool: &sqlx::PgPool,
-    ) -> Result<Self, anyhow::Error> {
+    ) -> Result<Self, AppError> {
+        if let Some(username) = &new_username {
+            validate_username(username)?;
+        }
         let username = new_username.unwrap_or(self.username.clone());
-        let email = new_email.unwrap_or(self.email.clone());
 
-        let user = sqlx::query_as!(
-            Self,
+        // A new email is only held as pending; it replaces the current one
+        // once confirmed. Submitting the current email cancels a pending change.
+        let pending_email = match new_email.map(|e| e.trim().to_owned()) {
+            Some(email) if email == self.email => None,
+            Some(email) => {
+                validate_email(&email)?;
+                if Self::email_claimed(&email, Some(self.id), pool).await? {
+                    return Err(AppError::Conflict("Username or email is already taken".into()));
+                }
+                Some(email)
+            }
+            None => self.pending_email.clone(),
+        };
+
+        let user = sqlx::query_as::<_, Self>(
             r#"
             UPDATE users
-            SET username = $1, email = $2, updated_at = NOW()
+            SET username = $1, pending_email = $2, updated_at = NOW()
             WHERE id = $3
             RETURNING *
             "#,
-            username,
-            email,
-            self.id
         )
+        .bind(username)
+        .bind(pending_email)
+        .bind(self.id)
         .fetch_one(pool)
-        .await?;
+        .await
+        .map_err(already_taken)?;
 
         Ok(user)
     }
+
+    /// Whether another user already has `email`, either as their address or
+    /// as a change still waiting to be confirmed.
+    async fn email_claimed(
+        email: &str,
+        except: Option<Uuid>,
+        pool: &sqlx::PgPool,
+    ) -> Result<bool, AppError> {
+        let claimed = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM users
+                WHERE (email = $1 OR pending_email = $1)
+                  AND ($2::UUID IS NULL OR id <> $2)
+            )
+            "#,
+        )
+        .bind(email)
+        .bind(except)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(claimed)
+    }
+}
+
+pub fn validate_username(username: &str) -> Result<(), AppError> {
+    let length = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length)
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || USERNAME_SYMBOLS.contains(&c))
+    {
+        return Err(AppError::ValidationError(format!(
+            "Username must be {} to {} letters, digits, '_', '.' or '-'",
+            MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// RFC 5322 syntax with a dotted domain; confirmation proves the address works.
+pub fn validate_email(email: &str) -> Result<(), AppError> {
+    let dotted_domain = email
+        .rsplit_once('@')
+        .is_some_and(|(_, domain)| domain.contains('.') && !domain.ends_with('.'));
+    if email.len() > MAX_EMAIL_LENGTH || !dotted_domain || !email.validate_email() {
+        return Err(AppError::ValidationError("Email address is not valid".into()));
+    }
+    Ok(())
+}
+
+/// Passwords need at least one letter and one digit or symbol, and must fit
+/// within bcrypt's input limit.
+pub fn validate_password(password: &str) -> Result<(), AppError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH || password.len() > MAX_PASSWORD_BYTES {
+        return Err(AppError::ValidationError(format!(
+            "Password must be at least {} characters and at most {} bytes",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_BYTES
+        )));
+    }
+    if !password.chars().any(char::is_alphabetic) || password.chars().all(char::is_alphabetic) {
+        return Err(AppError::ValidationError(
+            "Password must contain a letter and a digit or symbol".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Turns a unique-constraint violation on `users` into a conflict instead of
+/// surfacing the constraint name.
+fn already_taken(err: sqlx::Error) -> AppError {
+    match err {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            AppError::Conflict("Username or email is already taken".into())
+        }
+        err => err.into(),
+    }
 }
 
 
@@ -142,6 +424,9 @@ mod tests {
             password_hash: "hashed".to_string(),
             created_at: now,
             updated_at: now,
+            role: Role::Customer,
+            email_verified_at: None,
+            pending_email: None,
         };
 
         assert_eq!(user.id, id);
@@ -150,5 +435,93 @@ mod tests {
         assert_eq!(user.password_hash, "hashed");
         assert_eq!(user.created_at, now);
         assert_eq!(user.updated_at, now);
+        assert_eq!(user.role, Role::Customer);
+        assert!(!user.is_email_verified());
+    }
+
+    #[test]
+    fn test_email_validation() {
+        assert!(validate_email("alice@example.com").is_ok());
+        assert!(validate_email("a.b+tag@sub.example.co.uk").is_ok());
+        assert!(validate_email("1234.com").is_err());
+        assert!(validate_email("alice@localhost").is_err());
+        assert!(validate_email("@example.com").is_err());
+        assert!(validate_email("al ice@example.com").is_err());
+        assert!(validate_email("a@b@example.com").is_err());
+        assert!(validate_email("alice@example.").is_err());
+    }
+
+    #[test]
+    fn test_password_strength() {
+        assert!(validate_password("longenough").is_err());
+        assert!(validate_password("1234567890").is_err());
+        assert!(validate_password("correct-horse").is_ok());
+        assert!(validate_password("hunter2hunter2").is_ok());
+    }
+
+    #[test]
+    fn test_username_rules() {
+        assert!(validate_username("alice").is_ok());
+        assert!(validate_username("a.b-c_9").is_ok());
+        assert!(validate_username("al").is_err());
+        assert!(validate_username("").is_err());
+        assert!(validate_username("alice smith").is_err());
+        assert!(validate_username("alice@example").is_err());
+        assert!(validate_username(&"a".repeat(MAX_USERNAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_password_length_limits() {
+        assert!(validate_password("short1").is_err());
+        assert!(validate_password("longenough1").is_ok());
+        assert!(validate_password(&format!("{}1", "a".repeat(MAX_PASSWORD_BYTES - 1))).is_ok());
+        assert!(validate_password(&format!("{}1", "a".repeat(MAX_PASSWORD_BYTES))).is_err());
+    }
+
+    #[test]
+    fn test_role_serialization_and_staff() {
+        assert_eq!(serde_json::to_string(&Role::Auditor).unwrap(), "\"auditor\"");
+        assert!(!Role::Customer.is_staff());
+        assert!(Role::Support.is_staff());
+        assert!(Role::Admin.is_staff());
+        assert!(Role::Auditor.is_staff());
     }
-}
\ No newline at end of file
+
+    #[sqlx::test]
+    #[ignore = "needs a database, set DATABASE_URL"]
+    async fn test_pending_emails_cannot_be_claimed_twice(pool: sqlx::PgPool) {
+        let register = |name: &str| {
+            User::register(
+                name.into(),
+                format!("{}@example.com", name),
+                "correct-horse".into(),
+                &pool,
+            )
+        };
+        let (alice, _) = register("alice").await.unwrap();
+        let (bob, _) = register("bob").await.unwrap();
+
+        let alice = alice
+            .update_profile(None, Some("shared@example.com".into()), &pool)
+            .await
+            .unwrap();
+        assert_eq!(alice.pending_email.as_deref(), Some("shared@example.com"));
+        // Resubmitting your own pending address is fine.
+        assert!(alice.update_profile(None, Some("shared@example.com".into()), &pool).await.is_ok());
+
+        let err = bob
+            .update_profile(None, Some("shared@example.com".into()), &pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+        let err = User::register(
+            "carol".into(),
+            "shared@example.com".into(),
+            "correct-horse".into(),
+            &pool,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+    }
+}
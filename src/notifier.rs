@@ -0,0 +1,124 @@
+use crate::error::AppError;
+use crate::settings::{NotifierKind, NotifierSettings};
+use actix_web::web;
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Default file for [`FileNotifier`] when `notifier.file_path` is unset.
+pub const DEFAULT_NOTIFICATION_FILE: &str = "notifications.log";
+
+/// A message for a user, e.g. a password reset email.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers notifications to users. Implement this to plug in a real email
+/// or SMS provider. `send` runs on a server worker, so slow I/O must be
+/// awaited or moved to the blocking pool, never done inline.
+pub trait Notifier: Send + Sync {
+    fn send<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> LocalBoxFuture<'a, Result<(), AppError>>;
+}
+
+/// Records that a notification was sent, in the application log. The body
+/// is left out because it may hold secrets such as reset tokens; use
+/// [`FileNotifier`] to read messages in development.
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn send<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> LocalBoxFuture<'a, Result<(), AppError>> {
+        tracing::info!(to = %notification.to, subject = %notification.subject, "Notification");
+        future::ready(Ok(())).boxed_local()
+    }
+}
+
+/// Appends notifications to a file as JSON lines. For local development and tests.
+pub struct FileNotifier {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl FileNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileNotifier {
+            path: path.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn send<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> LocalBoxFuture<'a, Result<(), AppError>> {
+        let path = self.path.clone();
+        let lock = Arc::clone(&self.lock);
+        async move {
+            let line = serde_json::to_string(notification)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            web::block(move || append_line(&path, &line, &lock))
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?
+        }
+        .boxed_local()
+    }
+}
+
+/// Blocking; runs on the blocking thread pool.
+fn append_line(path: &Path, line: &str, lock: &Mutex<()>) -> Result<(), AppError> {
+    let _guard = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    writeln!(file, "{}", line).map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Builds the notifier chosen by `notifier.kind`.
+pub fn from_settings(settings: &NotifierSettings) -> Arc<dyn Notifier> {
+    match settings.kind {
+        NotifierKind::File => Arc::new(FileNotifier::new(&settings.file_path)),
+        NotifierKind::Log => Arc::new(LogNotifier),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_file_notifier_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("notifier-test-{}.log", uuid::Uuid::new_v4()));
+        let notifier = FileNotifier::new(&path);
+        let notification = Notification {
+            to: "alice@example.com".into(),
+            subject: "Hello".into(),
+            body: "First".into(),
+        };
+
+        notifier.send(&notification).await.unwrap();
+        notifier.send(&notification).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed["to"], "alice@example.com");
+        assert_eq!(parsed["subject"], "Hello");
+    }
+}
@@ -0,0 +1,204 @@
+use actix_web::http::StatusCode;
+use utoipa::openapi::path::Operation;
+use utoipa::openapi::response::ResponseBuilder;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Content, Ref, RefOr};
+use utoipa::{Modify, OpenApi};
+
+use crate::error::PROBLEM_CONTENT_TYPE;
+use crate::routes::{
+    accounts, admin, api_keys, balance, health, holds, mfa, sessions, transactions,
+    transfers, user, well_known,
+};
+
+/// Where the generated document is served.
+pub const OPENAPI_PATH: &str = "/api/openapi.json";
+/// Where the embedded Swagger UI is served.
+pub const DOCS_PATH: &str = "/api/docs/";
+/// Name of the security scheme protected operations refer to.
+pub const BEARER_AUTH: &str = "bearer_auth";
+
+/// The OpenAPI 3 document for the API, generated from the route handlers
+/// and their payload types. Every protected operation lists the scope it
+/// needs under `bearer_auth`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Banking API",
+        description = "Accounts, transactions, transfers and holds, with JWT and API key authentication."
+    ),
+    paths(
+        health::live,
+        health::ready,
+        well_known::jwks,
+        user::register,
+        user::login,
+        user::login_mfa,
+        user::refresh_token,
+        user::forgot_password,
+        user::reset_password,
+        user::verify_email,
+        user::logout,
+        sessions::list_sessions,
+        sessions::revoke_session,
+        mfa::enroll_totp,
+        mfa::confirm_totp,
+        mfa::disable_totp,
+        user::resend_email_verification,
+        api_keys::create_api_key,
+        api_keys::list_api_keys,
+        api_keys::revoke_api_key,
+        user::get_profile,
+        user::update_profile,
+        user::change_password,
+        accounts::open_account,
+        accounts::list_accounts,
+        accounts::get_account,
+        accounts::close_account,
+        accounts::get_account_ledger,
+        transactions::create_transaction,
+        transactions::get_user_transactions,
+        transactions::get_transaction,
+        transactions::reverse_transaction,
+        transfers::create_transfer,
+        holds::authorize_hold,
+        holds::list_holds,
+        holds::get_hold,
+        holds::capture_hold,
+        holds::void_hold,
+        balance::get_balance,
+        admin::search_users,
+        admin::get_user,
+        admin::unlock_user,
+        admin::list_login_attempts,
+        admin::get_account_balance,
+        admin::get_account_transactions,
+        admin::adjust_account,
+        admin::reverse_transaction,
+    ),
+    modifiers(&SecurityAndErrors),
+    tags(
+        (name = "health", description = "Probes for operators"),
+        (name = "auth", description = "Registration, login and account recovery"),
+        (name = "sessions", description = "Signed-in devices"),
+        (name = "mfa", description = "TOTP two-factor authentication"),
+        (name = "api-keys", description = "Credentials for server-to-server integrations"),
+        (name = "profile"),
+        (name = "accounts"),
+        (name = "transactions", description = "Credits, debits, reversals and transfers"),
+        (name = "holds", description = "Authorization holds"),
+        (name = "balance"),
+        (name = "admin", description = "Back office; also requires a staff role"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the bearer scheme checked by `jwt_validator` and fills in what
+/// the handler annotations leave out: the 401/403 answers every protected
+/// operation can give, the problem+json content type of error bodies and
+/// default response descriptions.
+struct SecurityAndErrors;
+
+impl Modify for SecurityAndErrors {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                BEARER_AUTH,
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .description(Some(
+                            "An access token from /api/login, or an API key (`bk_...`). \
+                             Each operation lists the scope it needs.",
+                        ))
+                        .build(),
+                ),
+            );
+
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [&mut item.get, &mut item.put, &mut item.post, &mut item.delete];
+            for operation in operations.into_iter().flatten() {
+                if operation.security.is_some() {
+                    add_problem(operation, "401", "Missing, invalid or revoked credential");
+                    add_problem(operation, "403", "The credential lacks the required scope or role");
+                }
+                for (status, response) in operation.responses.responses.iter_mut() {
+                    let RefOr::T(response) = response else {
+                        continue;
+                    };
+                    if response.description.is_empty() {
+                        response.description = status
+                            .parse()
+                            .ok()
+                            .and_then(|code| StatusCode::from_u16(code).ok())
+                            .and_then(|code| code.canonical_reason())
+                            .unwrap_or_default()
+                            .to_owned();
+                    }
+                    if let Some(content) = response.content.shift_remove("application/json") {
+                        let content_type = if is_problem(&content) {
+                            PROBLEM_CONTENT_TYPE
+                        } else {
+                            "application/json"
+                        };
+                        response.content.insert(content_type.to_owned(), content);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn add_problem(operation: &mut Operation, status: &str, description: &str) {
+    operation
+        .responses
+        .responses
+        .entry(status.to_owned())
+        .or_insert_with(|| {
+            ResponseBuilder::new()
+                .description(description)
+                .content("application/json", Content::new(Some(Ref::from_schema_name("Problem"))))
+                .build()
+                .into()
+        });
+}
+
+fn is_problem(content: &Content) -> bool {
+    matches!(&content.schema, Some(RefOr::Ref(r)) if r.ref_location.ends_with("/Problem"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_covers_payloads_and_security() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        let schemas = &doc["components"]["schemas"];
+        for name in [
+            "RegisterRequest",
+            "LoginRequest",
+            "UpdateProfileRequest",
+            "CreateTransactionRequest",
+            "Transaction",
+            "AccountBalance",
+            "User",
+        ] {
+            assert!(schemas.get(name).is_some(), "missing schema {}", name);
+        }
+        assert!(schemas["User"]["properties"].get("password_hash").is_none());
+        assert_eq!(
+            doc["components"]["securitySchemes"][BEARER_AUTH]["scheme"],
+            "bearer"
+        );
+
+        let balance = &doc["paths"]["/api/balance"]["get"];
+        assert_eq!(balance["security"][0][BEARER_AUTH][0], "balance:read");
+        assert!(balance["responses"]["403"]["content"][PROBLEM_CONTENT_TYPE].is_object());
+        assert!(doc["paths"]["/api/login"]["post"].get("security").is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::auth::AuthenticatedUser;
+use crate::error::{AppError, Problem};
+use crate::models::account::MAX_ACCOUNT_NAME_LENGTH;
+use crate::models::{Account, AccountBalance, AccountType, BalanceVerification, Ledger, Posting};
+use crate::validation::ValidatedJson;
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct AccountLedger {
+    #[serde(flatten)]
+    verification: BalanceVerification,
+    postings: Vec<Posting>,
+}
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct OpenAccountRequest {
+    account_type: AccountType,
+    #[validate(length(min = 1, max = MAX_ACCOUNT_NAME_LENGTH))]
+    name: Option<String>, // Defaults to the account type, e.g. "Savings"
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/accounts",
+    tag = "accounts",
+    request_body = OpenAccountRequest,
+    security(("bearer_auth" = ["accounts:write"])),
+    responses(
+        (status = 201, body = Account),
+        (status = 400, description = "Invalid fields", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn open_account(
+    user: AuthenticatedUser,
+    payload: ValidatedJson<OpenAccountRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let account =
+        Account::open(user.user_id, payload.account_type, payload.name.clone(), &pool).await?;
+    Ok(HttpResponse::Created().json(account))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/accounts",
+    tag = "accounts",
+    security(("bearer_auth" = ["accounts:read"])),
+    responses((status = 200, body = [Account]))
+)]
+#[instrument(skip_all)]
+pub async fn list_accounts(
+    user: AuthenticatedUser,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let accounts = Account::get_by_user(user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(accounts))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/accounts/{id}",
+    tag = "accounts",
+    security(("bearer_auth" = ["accounts:read"])),
+    responses((status = 200, body = Account), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_account(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let account = Account::get_by_id(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/accounts/{id}/close",
+    tag = "accounts",
+    security(("bearer_auth" = ["accounts:write"])),
+    responses(
+        (status = 200, body = Account),
+        (status = 404, body = Problem),
+        (status = 409, description = "Non-zero balance, pending holds or the primary account", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn close_account(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let account = Account::close(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/accounts/{id}/ledger",
+    tag = "accounts",
+    security(("bearer_auth" = ["accounts:read"])),
+    responses((status = 200, body = AccountLedger), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_account_ledger(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let account_id = path.into_inner();
+    let verification = AccountBalance::verify(account_id, user.user_id, &pool).await?;
+    let postings = Ledger::get_postings(account_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(AccountLedger {
+        verification,
+        postings,
+    }))
+}
@@ -0,0 +1,232 @@
+use crate::auth::{Admin, AuthorizedUser, Staff};
+use crate::error::{AppError, Problem};
+use crate::models::{
+    Account, AccountBalance, Adjustment, LoginAttempt, LoginThrottle, Reversal, ThrottleScope,
+    Transaction, TransactionPage, TransactionType, User,
+};
+use crate::models::adjustment::MAX_REASON_LENGTH;
+use crate::models::transaction::MAX_AMOUNT;
+use crate::routes::transactions::TransactionHistoryQuery;
+use crate::settings::Limits;
+use crate::validation::ValidatedJson;
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct UserDetails {
+    #[serde(flatten)]
+    user: User,
+    accounts: Vec<Account>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserSearchQuery {
+    q: Option<String>, // Substring of the username or email
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LoginAttemptQuery {
+    email: Option<String>,
+    ip_address: Option<String>,
+}
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct AdjustmentRequest {
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: i64,
+    transaction_type: TransactionType,
+    #[validate(length(min = 1, max = MAX_REASON_LENGTH))]
+    reason: String,
+}
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct StaffReversalRequest {
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: Option<i64>, // Defaults to the full remaining amount
+    #[validate(length(min = 1, max = MAX_REASON_LENGTH))]
+    reason: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    tag = "admin",
+    params(UserSearchQuery),
+    security(("bearer_auth" = ["admin:read"])),
+    responses((status = 200, body = [User]))
+)]
+#[instrument(skip_all)]
+pub async fn search_users(
+    _staff: AuthorizedUser<Staff>,
+    query: web::Query<UserSearchQuery>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let users = User::search(query.q.as_deref(), &pool).await?;
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}",
+    tag = "admin",
+    security(("bearer_auth" = ["admin:read"])),
+    responses((status = 200, body = UserDetails), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_user(
+    _staff: AuthorizedUser<Staff>,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let user = User::get_by_id(&path.into_inner(), &pool).await?;
+    let accounts = Account::get_by_user(user.id, &pool).await?;
+    Ok(HttpResponse::Ok().json(UserDetails { user, accounts }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/accounts/{id}/balance",
+    tag = "admin",
+    security(("bearer_auth" = ["admin:read"])),
+    responses((status = 200, body = AccountBalance), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_account_balance(
+    _staff: AuthorizedUser<Staff>,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let account = Account::find(path.into_inner(), &pool).await?;
+    let balance = AccountBalance::get_balance(account.id, account.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(balance))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/accounts/{id}/transactions",
+    tag = "admin",
+    params(TransactionHistoryQuery),
+    security(("bearer_auth" = ["admin:read"])),
+    responses((status = 200, body = TransactionPage), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_account_transactions(
+    _staff: AuthorizedUser<Staff>,
+    path: web::Path<Uuid>,
+    query: web::Query<TransactionHistoryQuery>,
+    limits: web::Data<Limits>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let account = Account::find(path.into_inner(), &pool).await?;
+    let (mut filter, cursor, limit) = query.into_inner().into_parts(&limits)?;
+    filter.account_id = Some(account.id);
+    let page = Transaction::get_by_user(account.user_id, &filter, cursor, limit, &pool).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/accounts/{id}/adjustments",
+    tag = "admin",
+    request_body = AdjustmentRequest,
+    security(("bearer_auth" = ["admin:write"])),
+    responses(
+        (status = 201, body = Adjustment),
+        (status = 400, description = "Invalid fields or insufficient funds", body = Problem),
+        (status = 404, body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn adjust_account(
+    admin: AuthorizedUser<Admin>,
+    path: web::Path<Uuid>,
+    payload: ValidatedJson<AdjustmentRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let adjustment = Adjustment::create(
+        admin.user.user_id,
+        path.into_inner(),
+        payload.amount,
+        payload.transaction_type,
+        &payload.reason,
+        &pool,
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(adjustment))
+}
+
+/// Reverses any transaction, including debits, hold captures and adjustments
+/// that customers can't reverse themselves.
+#[utoipa::path(
+    post,
+    path = "/api/admin/transactions/{id}/reverse",
+    tag = "admin",
+    request_body = StaffReversalRequest,
+    security(("bearer_auth" = ["admin:write"])),
+    responses(
+        (status = 200, body = Reversal),
+        (status = 400, description = "Invalid fields or insufficient funds", body = Problem),
+        (status = 404, body = Problem),
+        (status = 409, description = "Already fully reversed, or more than remains", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn reverse_transaction(
+    admin: AuthorizedUser<Admin>,
+    path: web::Path<Uuid>,
+    payload: ValidatedJson<StaffReversalRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let reversal = Reversal::create_by_staff(
+        admin.user.user_id,
+        path.into_inner(),
+        payload.amount,
+        &payload.reason,
+        &pool,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(reversal))
+}
+
+/// Lifts a lockout on a user's email before it expires on its own.
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/unlock",
+    tag = "admin",
+    security(("bearer_auth" = ["admin:write"])),
+    responses((status = 204, description = "Lockout lifted"), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn unlock_user(
+    _admin: AuthorizedUser<Admin>,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let user = User::get_by_id(&path.into_inner(), &pool).await?;
+    LoginThrottle::clear(ThrottleScope::Email, &user.email, &pool).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/login-attempts",
+    tag = "admin",
+    params(LoginAttemptQuery),
+    security(("bearer_auth" = ["admin:read"])),
+    responses((status = 200, body = [LoginAttempt]))
+)]
+#[instrument(skip_all)]
+pub async fn list_login_attempts(
+    _staff: AuthorizedUser<Staff>,
+    query: web::Query<LoginAttemptQuery>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let attempts =
+        LoginAttempt::search(query.email.as_deref(), query.ip_address.as_deref(), &pool).await?;
+    Ok(HttpResponse::Ok().json(attempts))
+}
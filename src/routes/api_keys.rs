@@ -0,0 +1,90 @@
+use crate::auth::{AuthenticatedUser, Scope};
+use crate::error::{AppError, Problem};
+use crate::models::{ApiKey, CreatedApiKey};
+use crate::models::api_key::{MAX_API_KEY_NAME_LENGTH, MAX_API_KEY_TTL_DAYS};
+use crate::settings::Limits;
+use crate::validation::ValidatedJson;
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(min = 1, max = MAX_API_KEY_NAME_LENGTH))]
+    name: String,
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    scopes: Vec<Scope>,
+    #[validate(range(min = 1, max = MAX_API_KEY_TTL_DAYS))]
+    expires_in_days: Option<i64>, // Defaults to limits.default_api_key_ttl_days
+}
+
+/// The response is the only time the key's secret is shown.
+#[utoipa::path(
+    post,
+    path = "/api/api-keys",
+    tag = "api-keys",
+    request_body = CreateApiKeyRequest,
+    security(("bearer_auth" = ["credentials:write"])),
+    responses(
+        (status = 201, body = CreatedApiKey),
+        (status = 400, description = "Invalid fields or a scope keys cannot hold", body = Problem),
+        (status = 409, description = "Too many active keys", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn create_api_key(
+    user: AuthenticatedUser,
+    payload: ValidatedJson<CreateApiKeyRequest>,
+    limits: web::Data<Limits>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let key = ApiKey::create(
+        user.user_id,
+        &payload.name,
+        &payload.scopes,
+        payload.expires_in_days,
+        &limits,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(key))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/api-keys",
+    tag = "api-keys",
+    security(("bearer_auth" = ["credentials:write"])),
+    responses((status = 200, body = [ApiKey]))
+)]
+#[instrument(skip_all)]
+pub async fn list_api_keys(
+    user: AuthenticatedUser,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let keys = ApiKey::get_by_user(user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/api-keys/{id}",
+    tag = "api-keys",
+    security(("bearer_auth" = ["credentials:write"])),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 404, body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn revoke_api_key(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    ApiKey::revoke(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,13 +1,49 @@
-use crate::error::AppError;
+use crate::error::{AppError, Problem};
+use crate::models::Account;
 use crate::models::account_balance::AccountBalance;
 use actix_web::{HttpResponse, Responder, web};
 use crate::auth::AuthenticatedUser;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
 
+#[derive(serde::Serialize, ToSchema)]
+pub struct BalanceResponse {
+    #[serde(flatten)]
+    balance: AccountBalance,
+    ledger: i64,    // Settled balance, including funds reserved by holds
+    available: i64, // What can still be spent: ledger minus held
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BalanceQuery {
+    account_id: Option<Uuid>, // Defaults to the user's primary account
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/balance",
+    tag = "balance",
+    params(BalanceQuery),
+    security(("bearer_auth" = ["balance:read"])),
+    responses((status = 200, body = BalanceResponse), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
 pub async fn get_balance(
     user: AuthenticatedUser,
+    query: web::Query<BalanceQuery>,
     pool: web::Data<sqlx::PgPool>,
 ) -> Result<impl Responder, AppError> {
     let user_id = user.user_id;
-    let balance = AccountBalance::get_balance(user_id, &pool).await?;
-    Ok(HttpResponse::Ok().json(balance))
+    let account_id = match query.account_id {
+        Some(account_id) => account_id,
+        None => Account::get_primary(user_id, &pool).await?.id,
+    };
+    let balance = AccountBalance::get_balance(account_id, user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(BalanceResponse {
+        ledger: balance.balance,
+        available: balance.available(),
+        balance,
+    }))
 }
@@ -0,0 +1,249 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::rt::time::timeout;
+use actix_web::{HttpResponse, Responder, web};
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// The migrations this build expects, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Upper bound on each readiness check, so a hung database fails the probe
+/// instead of stalling it.
+pub const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Up,
+    Down,
+}
+
+/// The outcome of probing one dependency.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Check {
+    pub status: CheckStatus,
+    /// Why the check failed. Details are logged, not returned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+/// Migrations this build ships that the database has not applied.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MigrationsCheck {
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub expected: usize,
+    pub pending: Vec<i64>,
+}
+
+/// Connection pool usage. Informational: a busy pool alone doesn't fail readiness.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PoolCheck {
+    pub status: CheckStatus,
+    pub size: u32,
+    pub idle: u32,
+    pub in_use: u32,
+    pub max: u32,
+    /// `in_use / max`, from 0 to 1.
+    pub saturation: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    pub database: Check,
+    pub migrations: MigrationsCheck,
+    pub pool: PoolCheck,
+}
+
+/// Liveness: the process is up and serving requests. Never touches the database.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = 200, description = "The server is running", body = Object))
+)]
+pub async fn live() -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(serde_json::json!({ "status": "alive" }))
+}
+
+/// Readiness: the database answers and its schema matches this build.
+/// Answers 503 otherwise, so load balancers drain traffic during outages.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = Readiness),
+        (status = 503, description = "A dependency is unavailable", body = Readiness),
+    )
+)]
+pub async fn ready(pool: web::Data<sqlx::PgPool>) -> impl Responder {
+    let database = check_database(&pool).await;
+    let migrations = if database.status == CheckStatus::Up {
+        check_migrations(&pool).await
+    } else {
+        MigrationsCheck::unknown("database unavailable".to_owned())
+    };
+    let options = pool.options();
+    let pool = pool_check(pool.size(), pool.num_idle(), options.get_max_connections());
+
+    let ready = database.status == CheckStatus::Up && migrations.status == CheckStatus::Up;
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(Readiness {
+            ready,
+            database,
+            migrations,
+            pool,
+        })
+}
+
+async fn check_database(pool: &sqlx::PgPool) -> Check {
+    let started = Instant::now();
+    match bounded(sqlx::query("SELECT 1").execute(pool)).await {
+        Ok(_) => Check {
+            status: CheckStatus::Up,
+            error: None,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+        },
+        Err(error) => Check {
+            status: CheckStatus::Down,
+            error: Some(error),
+            latency_ms: None,
+        },
+    }
+}
+
+async fn check_migrations(pool: &sqlx::PgPool) -> MigrationsCheck {
+    let applied = bounded(
+        sqlx::query_as::<_, (i64, bool)>("SELECT version, success FROM _sqlx_migrations")
+            .fetch_all(pool),
+    )
+    .await;
+    match applied {
+        Ok(rows) => migrations_check(&rows),
+        // Also the answer before `sqlx migrate run` has created the table
+        Err(error) => MigrationsCheck::unknown(error),
+    }
+}
+
+fn expected_migrations() -> impl Iterator<Item = i64> {
+    MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+}
+
+impl MigrationsCheck {
+    /// The state of the schema couldn't be read.
+    fn unknown(error: String) -> Self {
+        MigrationsCheck {
+            status: CheckStatus::Down,
+            error: Some(error),
+            expected: expected_migrations().count(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// Compares the embedded migrations with the `(version, success)` rows of
+/// `_sqlx_migrations`.
+fn migrations_check(applied: &[(i64, bool)]) -> MigrationsCheck {
+    let applied: HashMap<i64, bool> = applied.iter().copied().collect();
+    let expected: Vec<i64> = expected_migrations().collect();
+    let pending: Vec<i64> = expected
+        .iter()
+        .copied()
+        .filter(|version| applied.get(version) != Some(&true))
+        .collect();
+
+    MigrationsCheck {
+        status: if pending.is_empty() {
+            CheckStatus::Up
+        } else {
+            CheckStatus::Down
+        },
+        error: (!pending.is_empty()).then(|| format!("{} migrations not applied", pending.len())),
+        expected: expected.len(),
+        pending,
+    }
+}
+
+fn pool_check(size: u32, idle: usize, max: u32) -> PoolCheck {
+    let idle = (idle as u32).min(size);
+    let in_use = size - idle;
+    PoolCheck {
+        status: CheckStatus::Up,
+        size,
+        idle,
+        in_use,
+        max,
+        saturation: if max == 0 {
+            0.0
+        } else {
+            f64::from(in_use) / f64::from(max)
+        },
+    }
+}
+
+/// Runs a database call under [`READINESS_CHECK_TIMEOUT`], logging failures.
+async fn bounded<T>(call: impl Future<Output = Result<T, sqlx::Error>>) -> Result<T, String> {
+    match timeout(READINESS_CHECK_TIMEOUT, call).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "Readiness check failed");
+            Err("unavailable".to_owned())
+        }
+        Err(_) => {
+            tracing::warn!(timeout = ?READINESS_CHECK_TIMEOUT, "Readiness check timed out");
+            Err("timed out".to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_must_all_succeed() {
+        let all: Vec<(i64, bool)> = MIGRATOR.iter().map(|m| (m.version, true)).collect();
+        let check = migrations_check(&all);
+        assert_eq!(check.status, CheckStatus::Up);
+        assert!(check.pending.is_empty());
+        assert_eq!(check.expected, all.len());
+
+        let mut partial = all.clone();
+        let last = partial.pop().unwrap();
+        partial.push((last.0, false));
+        let check = migrations_check(&partial);
+        assert_eq!(check.status, CheckStatus::Down);
+        assert_eq!(check.pending, [last.0]);
+
+        let check = migrations_check(&[]);
+        assert_eq!(check.pending.len(), all.len());
+    }
+
+    #[test]
+    fn test_pool_saturation() {
+        let pool = pool_check(5, 1, 10);
+        assert_eq!(pool.in_use, 4);
+        assert_eq!(pool.saturation, 0.4);
+        assert_eq!(pool_check(0, 0, 5).saturation, 0.0);
+    }
+}
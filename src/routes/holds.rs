@@ -0,0 +1,146 @@
+use crate::auth::AuthenticatedUser;
+use crate::error::{AppError, Problem};
+use crate::models::hold::MAX_HOLD_TTL_SECONDS;
+use crate::models::transaction::{MAX_AMOUNT, MAX_DESCRIPTION_LENGTH};
+use crate::models::{Capture, Hold, HoldStatus};
+use crate::settings::Limits;
+use crate::validation::ValidatedJson;
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct AuthorizeHoldRequest {
+    account_id: Uuid,
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: i64,
+    #[validate(length(max = MAX_DESCRIPTION_LENGTH))]
+    description: Option<String>,
+    #[validate(range(min = 1, max = MAX_HOLD_TTL_SECONDS))]
+    expires_in_seconds: Option<i64>, // Defaults to limits.default_hold_ttl_seconds
+}
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct CaptureHoldRequest {
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: Option<i64>, // Defaults to the full held amount
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HoldListQuery {
+    status: Option<HoldStatus>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/holds",
+    tag = "holds",
+    request_body = AuthorizeHoldRequest,
+    security(("bearer_auth" = ["transactions:write"])),
+    responses(
+        (status = 201, body = Hold),
+        (status = 400, description = "Invalid fields or insufficient available funds", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn authorize_hold(
+    user: AuthenticatedUser,
+    payload: ValidatedJson<AuthorizeHoldRequest>,
+    limits: web::Data<Limits>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let hold = Hold::authorize(
+        user.user_id,
+        payload.account_id,
+        payload.amount,
+        payload.description.clone(),
+        payload.expires_in_seconds,
+        &limits,
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(hold))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/holds",
+    tag = "holds",
+    params(HoldListQuery),
+    security(("bearer_auth" = ["transactions:read"])),
+    responses((status = 200, body = [Hold]))
+)]
+#[instrument(skip_all)]
+pub async fn list_holds(
+    user: AuthenticatedUser,
+    query: web::Query<HoldListQuery>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let holds = Hold::get_by_user(user.user_id, query.status, &pool).await?;
+    Ok(HttpResponse::Ok().json(holds))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/holds/{id}",
+    tag = "holds",
+    security(("bearer_auth" = ["transactions:read"])),
+    responses((status = 200, body = Hold), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_hold(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let hold = Hold::get_by_id(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(hold))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/holds/{id}/capture",
+    tag = "holds",
+    request_body = CaptureHoldRequest,
+    security(("bearer_auth" = ["transactions:write"])),
+    responses(
+        (status = 200, body = Capture),
+        (status = 404, body = Problem),
+        (status = 409, description = "The hold is no longer pending", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn capture_hold(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    payload: ValidatedJson<CaptureHoldRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let capture = Hold::capture(path.into_inner(), user.user_id, payload.amount, &pool).await?;
+    Ok(HttpResponse::Ok().json(capture))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/holds/{id}/void",
+    tag = "holds",
+    security(("bearer_auth" = ["transactions:write"])),
+    responses(
+        (status = 200, body = Hold),
+        (status = 404, body = Problem),
+        (status = 409, description = "The hold is no longer pending", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn void_hold(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let hold = Hold::void(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(hold))
+}
@@ -0,0 +1,11 @@
+use crate::error::AppError;
+use crate::metrics;
+use actix_web::{HttpResponse, Responder, web};
+use prometheus::TEXT_FORMAT;
+
+/// Request, database pool and business metrics in the Prometheus text format.
+/// Served on the metrics listener only.
+pub async fn metrics(pool: web::Data<sqlx::PgPool>) -> Result<impl Responder, AppError> {
+    let body = metrics::global().render(&pool)?;
+    Ok(HttpResponse::Ok().content_type(TEXT_FORMAT).body(body))
+}
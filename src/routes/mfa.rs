@@ -0,0 +1,104 @@
+use crate::auth::AuthenticatedUser;
+use crate::error::{AppError, Problem};
+use crate::models::{SecondFactor, TotpCredential, TotpEnrollment};
+use crate::validation::{MAX_CODE_LENGTH, ValidatedJson};
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use utoipa::ToSchema;
+use validator::Validate;
+
+/// A second factor as submitted by the client: exactly one of a TOTP `code`
+/// or a `recovery_code`.
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct SecondFactorRequest {
+    #[validate(length(min = 1, max = MAX_CODE_LENGTH))]
+    code: Option<String>,
+    #[validate(length(min = 1, max = MAX_CODE_LENGTH))]
+    recovery_code: Option<String>,
+}
+
+impl SecondFactorRequest {
+    pub(crate) fn factor(&self) -> Result<SecondFactor<'_>, AppError> {
+        match (&self.code, &self.recovery_code) {
+            (Some(code), None) => Ok(SecondFactor::Totp(code)),
+            (None, Some(code)) => Ok(SecondFactor::RecoveryCode(code)),
+            _ => Err(AppError::ValidationError(
+                "Provide either code or recovery_code".into(),
+            )),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct ConfirmTotpRequest {
+    #[validate(length(min = 1, max = MAX_CODE_LENGTH))]
+    code: String,
+}
+
+/// Single-use codes that stand in for a TOTP code if the device is lost.
+/// Only ever shown here.
+#[derive(serde::Serialize, ToSchema)]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/mfa/totp/enroll",
+    tag = "mfa",
+    security(("bearer_auth" = ["credentials:write"])),
+    responses(
+        (status = 200, body = TotpEnrollment),
+        (status = 409, description = "2FA is already on", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn enroll_totp(
+    user: AuthenticatedUser,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let enrollment = TotpCredential::enroll(user.user_id, &user.email, &pool).await?;
+    Ok(HttpResponse::Ok().json(enrollment))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/mfa/totp/confirm",
+    tag = "mfa",
+    request_body = ConfirmTotpRequest,
+    security(("bearer_auth" = ["credentials:write"])),
+    responses(
+        (status = 200, description = "2FA is on", body = RecoveryCodes),
+        (status = 401, description = "Invalid code", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn confirm_totp(
+    user: AuthenticatedUser,
+    payload: ValidatedJson<ConfirmTotpRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let recovery_codes = TotpCredential::confirm(user.user_id, &payload.code, &pool).await?;
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/mfa/totp/disable",
+    tag = "mfa",
+    request_body = SecondFactorRequest,
+    security(("bearer_auth" = ["credentials:write"])),
+    responses(
+        (status = 204, description = "2FA is off"),
+        (status = 401, description = "Invalid code", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn disable_totp(
+    user: AuthenticatedUser,
+    payload: ValidatedJson<SecondFactorRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    TotpCredential::disable(user.user_id, payload.factor()?, &pool).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,41 +1,272 @@
-use crate::auth::jwt::JwtService;
-use crate::auth::middleware::jwt_validator;
-use actix_web::web;
+use crate::auth::middleware::{jwt_validator, require_scope};
+use crate::auth::scopes::Scope;
+use crate::error::AppError;
+use crate::openapi::{ApiDoc, DOCS_PATH, OPENAPI_PATH};
+use actix_web::error::JsonPayloadError;
+use actix_web::middleware::from_fn;
+use actix_web::{HttpResponse, Route, web};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use std::env;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
+pub mod accounts;
+pub mod admin;
+pub mod api_keys;
 pub mod balance;
+pub mod health;
+pub mod holds;
+pub mod metrics;
+pub mod mfa;
+pub mod sessions;
 pub mod transactions;
+pub mod transfers;
 pub mod user;
+pub mod well_known;
 
+/// Registers every route. The pool, `JwtService`, `LoginPolicy`, `Limits`
+/// and notifier are built once from the settings and added as app data by
+/// the caller, so all workers share them.
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    let jwt_config = JwtService::from_env();
-    let auth = HttpAuthentication::bearer(jwt_validator);
+    let auth = HttpAuthentication::with_fn(jwt_validator);
 
-    cfg.app_data(web::Data::new(jwt_config)).service(
+    // Malformed bodies, query strings and paths are reported like any other
+    // error, with a fixed message: parser errors can quote the input back.
+    cfg.app_data(web::JsonConfig::default().error_handler(|err, _| json_error(&err).into()))
+        .app_data(web::QueryConfig::default().error_handler(|_, _| {
+            AppError::ValidationError("Malformed query string".into()).into()
+        }))
+        .app_data(web::PathConfig::default().error_handler(|_, _| {
+            AppError::ValidationError("Malformed path parameter".into()).into()
+        }))
+        .default_service(web::to(not_found))
+        .service(web::resource("/.well-known/jwks.json").route(web::get().to(well_known::jwks)))
+        .service(web::resource("/health/live").route(web::get().to(health::live)))
+        .service(web::resource("/health/ready").route(web::get().to(health::ready)))
+        // Registered ahead of /api so the scope doesn't claim these paths
+        .service(web::redirect(DOCS_PATH.trim_end_matches('/'), DOCS_PATH))
+        .service(
+            SwaggerUi::new(format!("{}{{_:.*}}", DOCS_PATH)).url(OPENAPI_PATH, ApiDoc::openapi()),
+        )
+        .service(
         web::scope("/api")
             // Public routes
             .service(web::resource("/register").route(web::post().to(user::register)))
             .service(web::resource("/login").route(web::post().to(user::login)))
-            // Protected routes
+            .service(web::resource("/login/mfa").route(web::post().to(user::login_mfa)))
+            .service(web::resource("/token/refresh").route(web::post().to(user::refresh_token)))
+            .service(
+                web::resource("/password/forgot").route(web::post().to(user::forgot_password)),
+            )
+            .service(web::resource("/password/reset").route(web::post().to(user::reset_password)))
+            .service(web::resource("/email/verify").route(web::post().to(user::verify_email)))
+            // Protected routes; each names the scope its credential must carry
             .service(
                 web::scope("")
                     .wrap(auth)
+                    .service(web::resource("/logout").route(web::post().to(user::logout)))
+                    .service(web::resource("/sessions").route(scoped(
+                        web::get().to(sessions::list_sessions),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(web::resource("/sessions/{id}").route(scoped(
+                        web::delete().to(sessions::revoke_session),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(web::resource("/mfa/totp/enroll").route(scoped(
+                        web::post().to(mfa::enroll_totp),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(web::resource("/mfa/totp/confirm").route(scoped(
+                        web::post().to(mfa::confirm_totp),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(web::resource("/mfa/totp/disable").route(scoped(
+                        web::post().to(mfa::disable_totp),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(web::resource("/email/verify/resend").route(scoped(
+                        web::post().to(user::resend_email_verification),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(
+                        web::resource("/api-keys")
+                            .route(scoped(
+                                web::post().to(api_keys::create_api_key),
+                                Scope::CredentialsWrite,
+                            ))
+                            .route(scoped(
+                                web::get().to(api_keys::list_api_keys),
+                                Scope::CredentialsWrite,
+                            )),
+                    )
+                    .service(web::resource("/api-keys/{id}").route(scoped(
+                        web::delete().to(api_keys::revoke_api_key),
+                        Scope::CredentialsWrite,
+                    )))
                     .service(
                         web::resource("/profile")
-                            .route(web::get().to(user::get_profile))
-                            .route(web::put().to(user::update_profile)),
+                            .route(scoped(web::get().to(user::get_profile), Scope::ProfileRead))
+                            .route(scoped(
+                                web::put().to(user::update_profile),
+                                Scope::ProfileWrite,
+                            )),
+                    )
+                    .service(web::resource("/profile/password").route(scoped(
+                        web::put().to(user::change_password),
+                        Scope::CredentialsWrite,
+                    )))
+                    .service(
+                        web::resource("/accounts")
+                            .route(scoped(
+                                web::post().to(accounts::open_account),
+                                Scope::AccountsWrite,
+                            ))
+                            .route(scoped(
+                                web::get().to(accounts::list_accounts),
+                                Scope::AccountsRead,
+                            )),
                     )
+                    .service(web::resource("/accounts/{id}").route(scoped(
+                        web::get().to(accounts::get_account),
+                        Scope::AccountsRead,
+                    )))
+                    .service(web::resource("/accounts/{id}/ledger").route(scoped(
+                        web::get().to(accounts::get_account_ledger),
+                        Scope::AccountsRead,
+                    )))
+                    .service(web::resource("/accounts/{id}/close").route(scoped(
+                        web::post().to(accounts::close_account),
+                        Scope::AccountsWrite,
+                    )))
                     .service(
                         web::resource("/transactions")
-                            .route(web::post().to(transactions::create_transaction))
-                            .route(web::get().to(transactions::get_user_transactions)),
+                            .route(scoped(
+                                web::post().to(transactions::create_transaction),
+                                Scope::TransactionsWrite,
+                            ))
+                            .route(scoped(
+                                web::get().to(transactions::get_user_transactions),
+                                Scope::TransactionsRead,
+                            )),
                     )
-                    // .service(
-                    //     web::resource("/transactions/{id}")
-                    //         .route(web::get().to(transactions::get_transaction)),
-                    // )
-                    .service(web::resource("/balance").route(web::get().to(balance::get_balance))),
+                    .service(web::resource("/transfers").route(scoped(
+                        web::post().to(transfers::create_transfer),
+                        Scope::TransactionsWrite,
+                    )))
+                    .service(web::resource("/transactions/{id}").route(scoped(
+                        web::get().to(transactions::get_transaction),
+                        Scope::TransactionsRead,
+                    )))
+                    .service(web::resource("/transactions/{id}/reverse").route(scoped(
+                        web::post().to(transactions::reverse_transaction),
+                        Scope::TransactionsWrite,
+                    )))
+                    .service(
+                        web::resource("/holds")
+                            .route(scoped(
+                                web::post().to(holds::authorize_hold),
+                                Scope::TransactionsWrite,
+                            ))
+                            .route(scoped(
+                                web::get().to(holds::list_holds),
+                                Scope::TransactionsRead,
+                            )),
+                    )
+                    .service(web::resource("/holds/{id}").route(scoped(
+                        web::get().to(holds::get_hold),
+                        Scope::TransactionsRead,
+                    )))
+                    .service(web::resource("/holds/{id}/capture").route(scoped(
+                        web::post().to(holds::capture_hold),
+                        Scope::TransactionsWrite,
+                    )))
+                    .service(web::resource("/holds/{id}/void").route(scoped(
+                        web::post().to(holds::void_hold),
+                        Scope::TransactionsWrite,
+                    )))
+                    .service(web::resource("/balance").route(scoped(
+                        web::get().to(balance::get_balance),
+                        Scope::BalanceRead,
+                    )))
+                    // Back-office routes; each handler enforces its required role
+                    .service(
+                        web::scope("/admin")
+                            .service(web::resource("/users").route(scoped(
+                                web::get().to(admin::search_users),
+                                Scope::AdminRead,
+                            )))
+                            .service(
+                                web::resource("/users/{id}").route(scoped(
+                                    web::get().to(admin::get_user),
+                                    Scope::AdminRead,
+                                )),
+                            )
+                            .service(web::resource("/users/{id}/unlock").route(scoped(
+                                web::post().to(admin::unlock_user),
+                                Scope::AdminWrite,
+                            )))
+                            .service(web::resource("/login-attempts").route(scoped(
+                                web::get().to(admin::list_login_attempts),
+                                Scope::AdminRead,
+                            )))
+                            .service(web::resource("/accounts/{id}/balance").route(scoped(
+                                web::get().to(admin::get_account_balance),
+                                Scope::AdminRead,
+                            )))
+                            .service(web::resource("/accounts/{id}/transactions").route(scoped(
+                                web::get().to(admin::get_account_transactions),
+                                Scope::AdminRead,
+                            )))
+                            .service(web::resource("/accounts/{id}/adjustments").route(scoped(
+                                web::post().to(admin::adjust_account),
+                                Scope::AdminWrite,
+                            )))
+                            .service(web::resource("/transactions/{id}/reverse").route(scoped(
+                                web::post().to(admin::reverse_transaction),
+                                Scope::AdminWrite,
+                            ))),
+                    ),
             ),
     );
 }
+
+/// Registers the operator endpoints served on the metrics listener, apart
+/// from the public API.
+pub fn configure_metrics(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/metrics").route(web::get().to(metrics::metrics)))
+        .default_service(web::to(not_found));
+}
+
+/// Wraps `route` so it is refused with 403 unless the credential carries `scope`.
+fn scoped(route: Route, scope: Scope) -> Route {
+    route.wrap(from_fn(move |req, next| require_scope(scope, req, next)))
+}
+
+fn json_error(err: &JsonPayloadError) -> AppError {
+    let message = match err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            "Request body is too large"
+        }
+        JsonPayloadError::ContentType => "Content type must be application/json",
+        _ => "Malformed JSON body",
+    };
+    AppError::ValidationError(message.into())
+}
+
+async fn not_found() -> Result<HttpResponse, AppError> {
+    Err(AppError::NotFound("Resource".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_errors_do_not_echo_the_input() {
+        let err = serde_json::from_str::<u32>(r#""hunter2""#).unwrap_err();
+        assert!(err.to_string().contains("hunter2"));
+
+        let error = json_error(&JsonPayloadError::Deserialize(err));
+        assert_eq!(error.to_string(), "Validation error: Malformed JSON body");
+    }
+}
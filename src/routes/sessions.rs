@@ -0,0 +1,58 @@
+use crate::auth::{AuthenticatedUser, Credential};
+use crate::error::{AppError, Problem};
+use crate::models::{Session, SessionSummary};
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Where the user is signed in. The session making the request is flagged
+/// as `current`.
+#[utoipa::path(
+    get,
+    path = "/api/sessions",
+    tag = "sessions",
+    security(("bearer_auth" = ["credentials:write"])),
+    responses((status = 200, body = [SessionSummary]))
+)]
+#[instrument(skip_all)]
+pub async fn list_sessions(
+    user: AuthenticatedUser,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let current = match user.credential {
+        Credential::Session(id) => Some(id),
+        Credential::ApiKey(_) => None,
+    };
+    let sessions: Vec<SessionSummary> = Session::list_active(user.user_id, &pool)
+        .await?
+        .into_iter()
+        .map(|session| SessionSummary {
+            current: Some(session.id) == current,
+            session,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Signs a device out. Its access tokens stop working right away and its
+/// refresh token can no longer be used.
+#[utoipa::path(
+    delete,
+    path = "/api/sessions/{id}",
+    tag = "sessions",
+    security(("bearer_auth" = ["credentials:write"])),
+    responses(
+        (status = 204, description = "Session revoked"),
+        (status = 404, description = "No such active session", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn revoke_session(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    Session::revoke(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,40 +1,229 @@
 use crate::auth::AuthenticatedUser;
-use crate::error::AppError;
-use crate::models::transaction::{Transaction, TransactionType};
-use actix_web::{HttpResponse, Responder, web};
+use crate::error::{AppError, Problem};
+use crate::models::{IdempotencyRequest, Reversal};
+use crate::settings::Limits;
+use crate::models::transaction::{
+    MAX_AMOUNT, MAX_DESCRIPTION_LENGTH, Transaction, TransactionCursor, TransactionFilter,
+    TransactionPage, TransactionType,
+};
+use crate::validation::ValidatedJson;
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use chrono::{DateTime, Utc};
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
+use validator::Validate;
 
-#[derive(serde::Deserialize)]
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(serde::Deserialize, serde::Serialize, Validate, ToSchema)]
 pub struct CreateTransactionRequest {
-    amount: i64, // String to avoid floating point precision issues
+    account_id: Uuid,
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: i64, // In minor units (cents), so no floating point is involved
     transaction_type: TransactionType,
+    #[validate(length(max = MAX_DESCRIPTION_LENGTH))]
     description: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/transactions",
+    tag = "transactions",
+    request_body = CreateTransactionRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replays return the first response instead of posting twice"),
+    ),
+    security(("bearer_auth" = ["transactions:write"])),
+    responses(
+        (status = 200, body = Transaction),
+        (status = 400, description = "Invalid fields or insufficient funds", body = Problem),
+        (status = 409, description = "Idempotency key reused with a different payload", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
 pub async fn create_transaction(
+    req: HttpRequest,
     user: AuthenticatedUser,
-    payload: web::Json<CreateTransactionRequest>,
+    payload: ValidatedJson<CreateTransactionRequest>,
     pool: web::Data<sqlx::PgPool>,
 ) -> Result<impl Responder, AppError> {
     //let amount = payload.amount.parse().map_err(|_| AppError::ValidationError("Invalid amount".into()))?;
     let amount = payload.amount;
-    let transaction = Transaction::create(
-        user.user_id,
-        amount,
-        payload.transaction_type,
-        payload.description.clone(),
-        &pool,
-    )
-    .await?;
+    let idempotency_key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|value| {
+            value.to_str().map_err(|_| {
+                AppError::ValidationError("Idempotency-Key must be visible ASCII".into())
+            })
+        })
+        .transpose()?;
+
+    let transaction = match idempotency_key {
+        Some(key) => {
+            // Hash the parsed payload rather than raw bytes so formatting differences don't matter.
+            let body = serde_json::to_vec(&*payload).expect("request serializes to JSON");
+            Transaction::create_idempotent(
+                user.user_id,
+                payload.account_id,
+                amount,
+                payload.transaction_type,
+                payload.description.clone(),
+                &IdempotencyRequest::new(key, &body),
+                &pool,
+            )
+            .await?
+        }
+        None => {
+            Transaction::create(
+                user.user_id,
+                payload.account_id,
+                amount,
+                payload.transaction_type,
+                payload.description.clone(),
+                &pool,
+            )
+            .await?
+        }
+    };
 
     Ok(HttpResponse::Ok().json(transaction))
 }
 
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionHistoryQuery {
+    account_id: Option<Uuid>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+    transaction_type: Option<TransactionType>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    min_amount: Option<i64>,
+    max_amount: Option<i64>,
+    description: Option<String>,
+}
+
+impl TransactionHistoryQuery {
+    pub(crate) fn into_parts(
+        self,
+        limits: &Limits,
+    ) -> Result<(TransactionFilter, Option<TransactionCursor>, i64), AppError> {
+        let limit = self.limit.unwrap_or(limits.default_page_size);
+        if !(1..=limits.max_page_size).contains(&limit) {
+            return Err(AppError::ValidationError(format!(
+                "limit must be between 1 and {}",
+                limits.max_page_size
+            )));
+        }
+        if let (Some(from), Some(to)) = (self.from, self.to)
+            && from > to
+        {
+            return Err(AppError::ValidationError("from must not be after to".into()));
+        }
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount)
+            && min > max
+        {
+            return Err(AppError::ValidationError(
+                "min_amount must not exceed max_amount".into(),
+            ));
+        }
+
+        let cursor = self
+            .cursor
+            .as_deref()
+            .map(TransactionCursor::decode)
+            .transpose()?;
+
+        let filter = TransactionFilter {
+            account_id: self.account_id,
+            transaction_type: self.transaction_type,
+            from: self.from,
+            to: self.to,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            description: self.description.filter(|d| !d.is_empty()),
+        };
+
+        Ok((filter, cursor, limit))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/transactions",
+    tag = "transactions",
+    params(TransactionHistoryQuery),
+    security(("bearer_auth" = ["transactions:read"])),
+    responses((status = 200, body = TransactionPage), (status = 400, body = Problem))
+)]
+#[instrument(skip_all)]
 pub async fn get_user_transactions(
     user: AuthenticatedUser,
+    query: web::Query<TransactionHistoryQuery>,
+    limits: web::Data<Limits>,
     pool: web::Data<sqlx::PgPool>,
 ) -> Result<impl Responder, AppError> {
     let user_id = user.user_id;
-    let transactions = Transaction::get_by_user(user_id, &pool).await?;
-    Ok(HttpResponse::Ok().json(transactions))
+    let (filter, cursor, limit) = query.into_inner().into_parts(&limits)?;
+    let page = Transaction::get_by_user(user_id, &filter, cursor, limit, &pool).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/transactions/{id}",
+    tag = "transactions",
+    security(("bearer_auth" = ["transactions:read"])),
+    responses((status = 200, body = Transaction), (status = 404, body = Problem))
+)]
+#[instrument(skip_all)]
+pub async fn get_transaction(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let transaction = Transaction::get_by_id(path.into_inner(), user.user_id, &pool).await?;
+    Ok(HttpResponse::Ok().json(transaction))
+}
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct ReverseTransactionRequest {
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: Option<i64>, // Defaults to the full remaining amount
+    #[validate(length(max = MAX_DESCRIPTION_LENGTH))]
+    description: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/transactions/{id}/reverse",
+    tag = "transactions",
+    request_body = ReverseTransactionRequest,
+    security(("bearer_auth" = ["transactions:write"])),
+    responses(
+        (status = 200, body = Reversal),
+        (status = 403, description = "A debit, hold capture or adjustment, which only staff can reverse", body = Problem),
+        (status = 404, body = Problem),
+        (status = 409, description = "Already fully reversed, or more than remains", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn reverse_transaction(
+    user: AuthenticatedUser,
+    path: web::Path<Uuid>,
+    payload: ValidatedJson<ReverseTransactionRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let reversal = Reversal::create(
+        user.user_id,
+        path.into_inner(),
+        payload.amount,
+        payload.description.clone(),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(reversal))
 }
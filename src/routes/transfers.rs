@@ -0,0 +1,52 @@
+use crate::auth::AuthenticatedUser;
+use crate::error::{AppError, Problem};
+use crate::models::Transfer;
+use crate::models::transaction::{MAX_AMOUNT, MAX_DESCRIPTION_LENGTH};
+use crate::validation::ValidatedJson;
+use actix_web::{HttpResponse, Responder, web};
+use tracing::instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(serde::Deserialize, Validate, ToSchema)]
+pub struct CreateTransferRequest {
+    from_account_id: Uuid,
+    #[validate(length(min = 1))]
+    recipient: String, // Username or email of the receiving user
+    #[validate(range(min = 1, max = MAX_AMOUNT))]
+    amount: i64,
+    #[validate(length(max = MAX_DESCRIPTION_LENGTH))]
+    description: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/transfers",
+    tag = "transactions",
+    request_body = CreateTransferRequest,
+    security(("bearer_auth" = ["transactions:write"])),
+    responses(
+        (status = 200, body = Transfer),
+        (status = 400, description = "Invalid fields or insufficient funds", body = Problem),
+        (status = 404, description = "Unknown account or recipient", body = Problem),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn create_transfer(
+    user: AuthenticatedUser,
+    payload: ValidatedJson<CreateTransferRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<impl Responder, AppError> {
+    let transfer = Transfer::create(
+        user.user_id,
+        payload.from_account_id,
+        &payload.recipient,
+        payload.amount,
+        payload.description.clone(),
+        &pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(transfer))
+}
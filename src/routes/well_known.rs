@@ -0,0 +1,24 @@
+use crate::auth::JwtService;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{HttpResponse, Responder, web};
+
+/// How long verifiers may cache the key set. Schedule new keys at least this
+/// far ahead so every verifier has them before they sign anything.
+pub const JWKS_MAX_AGE_SECONDS: u32 = 5 * 60;
+
+/// The public keys access tokens are signed with. Empty when tokens are
+/// signed with a shared HS256 secret.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "auth",
+    responses((status = 200, description = "A JSON Web Key Set", body = Object))
+)]
+pub async fn jwks(jwt_config: web::Data<JwtService>) -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(JWKS_MAX_AGE_SECONDS),
+        ]))
+        .json(jwt_config.jwks())
+}
@@ -0,0 +1,411 @@
+use config::{Config, ConfigError, Environment, File, FileFormat};
+use serde::Deserialize;
+use std::env;
+use std::path::PathBuf;
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+use validator::Validate;
+
+use crate::auth::lockout::LoginPolicy;
+use crate::error::{FieldError, field_errors};
+use crate::models::api_key::{DEFAULT_API_KEY_TTL_DAYS, MAX_API_KEY_TTL_DAYS, MAX_API_KEYS_PER_USER};
+use crate::models::hold::{DEFAULT_HOLD_TTL_SECONDS, MAX_HOLD_TTL_SECONDS};
+use crate::models::transaction::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+use crate::notifier::DEFAULT_NOTIFICATION_FILE;
+
+/// Names the settings file. Without it `config/default.toml` is read if present.
+pub const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+pub const DEFAULT_CONFIG_FILE: &str = "config/default.toml";
+/// Prefix of environment overrides; nested keys are joined with `__`,
+/// e.g. `APP_SERVER__PORT=9000` or `APP_DATABASE__MAX_CONNECTIONS=20`.
+pub const ENV_PREFIX: &str = "APP";
+
+/// Environment variables that predate the settings file. They still win
+/// over both the file and `APP_` overrides.
+const LEGACY_ENV_VARS: &[(&str, &str)] = &[
+    ("DATABASE_URL", "database.url"),
+    ("JWT_SECRET", "jwt.secret"),
+    ("JWT_KEYS_FILE", "jwt.keys_file"),
+    ("NOTIFIER", "notifier.kind"),
+    ("NOTIFIER_FILE_PATH", "notifier.file_path"),
+    ("LOGIN_MAX_FAILURES_PER_EMAIL", "login.max_failures_per_email"),
+    ("LOGIN_MAX_FAILURES_PER_IP", "login.max_failures_per_ip"),
+    ("LOGIN_LOCKOUT_SECONDS", "login.base_lockout_seconds"),
+    ("LOGIN_MAX_LOCKOUT_SECONDS", "login.max_lockout_seconds"),
+    ("LOGIN_FAILURE_WINDOW_SECONDS", "login.failure_window_seconds"),
+];
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("Failed to load settings: {0}")]
+    Load(#[from] ConfigError),
+    #[error("Invalid settings: {}", describe(.0))]
+    Invalid(Vec<FieldError>),
+}
+
+fn describe(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{}: {}", error.field, error.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Everything the server reads at startup. Every key has a default except
+/// the database URL and the JWT signing material.
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+#[serde(default)]
+pub struct Settings {
+    #[validate(nested)]
+    pub server: ServerSettings,
+    #[validate(nested)]
+    pub database: DatabaseSettings,
+    #[validate(nested)]
+    pub jwt: JwtSettings,
+    #[validate(nested)]
+    pub login: LoginPolicy,
+    pub notifier: NotifierSettings,
+    #[validate(nested)]
+    pub limits: Limits,
+    pub log: LogSettings,
+    #[validate(nested)]
+    pub metrics: MetricsSettings,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub host: String,
+    #[validate(range(min = 1))]
+    pub port: u16,
+    /// Worker threads; one per physical CPU when unset.
+    #[validate(range(min = 1))]
+    pub workers: Option<usize>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            host: "127.0.0.1".into(),
+            port: 8080,
+            workers: None,
+        }
+    }
+}
+
+/// Where `/metrics` is served. It has its own listener so that it stays off
+/// the public address; bind it to an interface only Prometheus can reach.
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct MetricsSettings {
+    pub host: String,
+    #[validate(range(min = 1))]
+    pub port: u16,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        MetricsSettings {
+            host: "127.0.0.1".into(),
+            port: 9090,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct DatabaseSettings {
+    #[validate(length(min = 1, message = "Must be set, e.g. with DATABASE_URL"))]
+    pub url: String,
+    #[validate(range(min = 1))]
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before failing.
+    #[validate(range(min = 1))]
+    pub acquire_timeout_seconds: u64,
+    /// Idle connections above `min_connections` are closed after this long.
+    #[validate(range(min = 1))]
+    pub idle_timeout_seconds: u64,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        DatabaseSettings {
+            url: String::new(),
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout_seconds: 30,
+            idle_timeout_seconds: 10 * 60,
+        }
+    }
+}
+
+/// Signing material and token lifetimes. Tokens are signed with the keys in
+/// the `keys_file` manifest when one is set, otherwise with HS256 and `secret`.
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct JwtSettings {
+    pub secret: Option<String>,
+    pub keys_file: Option<PathBuf>,
+    #[validate(range(min = 1, max = 1440))]
+    pub access_token_minutes: i64,
+    #[validate(range(min = 1, max = 365))]
+    pub refresh_token_days: i64,
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        JwtSettings {
+            secret: None,
+            keys_file: None,
+            access_token_minutes: 15,
+            refresh_token_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifierKind {
+    #[default]
+    Log,
+    File,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotifierSettings {
+    pub kind: NotifierKind,
+    /// Where the `file` notifier appends messages.
+    pub file_path: PathBuf,
+}
+
+impl Default for NotifierSettings {
+    fn default() -> Self {
+        NotifierSettings {
+            kind: NotifierKind::Log,
+            file_path: DEFAULT_NOTIFICATION_FILE.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line, for log collectors.
+    #[default]
+    Json,
+    /// Human-readable lines, for local development.
+    Pretty,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LogSettings {
+    /// An `EnvFilter` directive such as `info` or `banking_api=debug,sqlx=warn`.
+    /// `RUST_LOG` overrides it.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            level: "info".into(),
+            format: LogFormat::Json,
+        }
+    }
+}
+
+/// Business limits. The hard caps on request fields stay constants; these
+/// are the defaults and quotas within them.
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[serde(default)]
+pub struct Limits {
+    #[validate(range(min = 1))]
+    pub default_page_size: i64,
+    #[validate(range(min = 1, max = 1000))]
+    pub max_page_size: i64,
+    #[validate(range(min = 1))]
+    pub max_api_keys_per_user: i64,
+    #[validate(range(min = 1, max = MAX_API_KEY_TTL_DAYS))]
+    pub default_api_key_ttl_days: i64,
+    #[validate(range(min = 1, max = MAX_HOLD_TTL_SECONDS))]
+    pub default_hold_ttl_seconds: i64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            default_page_size: DEFAULT_PAGE_SIZE,
+            max_page_size: MAX_PAGE_SIZE,
+            max_api_keys_per_user: MAX_API_KEYS_PER_USER,
+            default_api_key_ttl_days: DEFAULT_API_KEY_TTL_DAYS,
+            default_hold_ttl_seconds: DEFAULT_HOLD_TTL_SECONDS,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings file, applies `APP_` and legacy environment
+    /// overrides and validates the result.
+    pub fn load() -> Result<Self, SettingsError> {
+        let (path, required) = match env::var(CONFIG_FILE_VAR) {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_FILE.to_owned(), false),
+        };
+        let mut builder = Config::builder()
+            .add_source(File::new(&path, FileFormat::Toml).required(required))
+            .add_source(
+                Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("_")
+                    .separator("__"),
+            );
+        for (var, key) in LEGACY_ENV_VARS {
+            let value = env::var(var).ok().filter(|value| !value.is_empty());
+            builder = builder.set_override_option(*key, value)?;
+        }
+
+        Self::from_config(builder.build()?)
+    }
+
+    fn from_config(config: Config) -> Result<Self, SettingsError> {
+        let settings: Settings = config.try_deserialize()?;
+        settings.check()?;
+        Ok(settings)
+    }
+
+    /// Field rules plus the constraints that span several fields.
+    fn check(&self) -> Result<(), SettingsError> {
+        let mut errors = match self.validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => field_errors(&errors),
+        };
+        let mut invalid = |field: &str, message: &str| {
+            errors.push(FieldError {
+                field: field.to_owned(),
+                code: "consistency".to_owned(),
+                message: message.to_owned(),
+            })
+        };
+
+        if self.database.min_connections > self.database.max_connections {
+            invalid("database.min_connections", "Must not exceed max_connections");
+        }
+        if self.jwt.keys_file.is_none() && self.jwt.secret.as_deref().is_none_or(str::is_empty) {
+            invalid("jwt.secret", "Must be set, e.g. with JWT_SECRET, unless jwt.keys_file is");
+        }
+        if self.login.base_lockout_seconds > self.login.max_lockout_seconds {
+            invalid("login.base_lockout_seconds", "Must not exceed max_lockout_seconds");
+        }
+        if self.limits.default_page_size > self.limits.max_page_size {
+            invalid("limits.default_page_size", "Must not exceed max_page_size");
+        }
+        if self.metrics.port == self.server.port {
+            invalid("metrics.port", "Must differ from server.port");
+        }
+        if EnvFilter::try_new(&self.log.level).is_err() {
+            invalid("log.level", "Is not a valid filter, e.g. info or banking_api=debug");
+        }
+
+        errors.sort_by(|a, b| a.field.cmp(&b.field));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError::Invalid(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Result<Settings, SettingsError> {
+        let config = Config::builder()
+            .add_source(File::from_str(toml, FileFormat::Toml))
+            .build()?;
+        Settings::from_config(config)
+    }
+
+    #[test]
+    fn test_partial_file_keeps_defaults() {
+        let settings = parse(
+            r#"
+            [server]
+            port = 9000
+
+            [database]
+            url = "postgres://localhost/banking"
+
+            [jwt]
+            secret = "dev"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.server.port, 9000);
+        assert_eq!(settings.server.host, "127.0.0.1");
+        assert_eq!(settings.database.max_connections, 5);
+        assert_eq!(settings.jwt.access_token_minutes, 15);
+        assert_eq!(settings.login.max_failures_per_email, 5);
+        assert_eq!(settings.notifier.kind, NotifierKind::Log);
+        assert_eq!(settings.limits.max_page_size, MAX_PAGE_SIZE);
+        assert_eq!(settings.metrics.port, 9090);
+    }
+
+    #[test]
+    fn test_invalid_settings_name_every_field() {
+        let err = parse(
+            r#"
+            [database]
+            max_connections = 2
+            min_connections = 4
+
+            [limits]
+            default_page_size = 500
+
+            [metrics]
+            port = 8080
+            "#,
+        )
+        .unwrap_err();
+
+        let SettingsError::Invalid(errors) = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "database.min_connections",
+                "database.url",
+                "jwt.secret",
+                "limits.default_page_size",
+                "metrics.port",
+            ]
+        );
+        assert!(err.to_string().contains("database.url: Must be set"));
+    }
+
+    #[test]
+    fn test_checked_in_defaults_parse() {
+        let file = include_str!("../config/default.toml");
+        let err = parse(file).unwrap_err();
+        // Only the secrets are left to the environment.
+        let SettingsError::Invalid(errors) = err else {
+            panic!("config/default.toml failed to parse");
+        };
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["database.url", "jwt.secret"]);
+
+        // The file sink writes tokens in plain text, so it is never the default.
+        let settings: Settings = Config::builder()
+            .add_source(File::from_str(file, FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(settings.notifier.kind, NotifierKind::Log);
+    }
+}
@@ -0,0 +1,121 @@
+use actix_web::{
+    Error,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use std::time::Instant;
+use tracing::Instrument;
+use tracing::field::{Empty, display};
+use tracing_subscriber::EnvFilter;
+
+use crate::request_id;
+use crate::settings::{LogFormat, LogSettings};
+
+/// Installs the global subscriber. `RUST_LOG`, when set, takes precedence
+/// over `log.level`. Records from the `log` crate, e.g. sqlx's, are
+/// forwarded into it. JSON lines list every enclosing span, so an event
+/// inside a model call still carries the request id and user id.
+pub fn init(settings: &LogSettings) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&settings.level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match settings.format {
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+        LogFormat::Pretty => builder.init(),
+    }
+}
+
+/// Middleware that runs each request inside a `request` span carrying its
+/// id, route and, once authenticated, the user id, and logs its outcome.
+/// Must run inside [`request_id::assign`].
+pub async fn trace_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let span = tracing::info_span!(
+        "request",
+        request_id = request_id::current().as_deref().unwrap_or("-"),
+        method = %req.method(),
+        route = req.match_pattern().as_deref().unwrap_or(req.path()),
+        peer = req.peer_addr().map(|addr| addr.ip().to_string()).as_deref().unwrap_or("-"),
+        user_id = Empty,
+    );
+    let started = Instant::now();
+
+    let result = next.call(req).instrument(span.clone()).await;
+    let status = match &result {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    span.in_scope(|| {
+        tracing::info!(
+            status = status.as_u16(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "request completed"
+        )
+    });
+
+    result
+}
+
+/// Attaches the authenticated user to the current request span.
+pub fn record_user(user_id: uuid::Uuid) {
+    tracing::Span::current().record("user_id", display(user_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_events_carry_the_request_and_user() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_writer(move || writer.clone())
+            .finish();
+        let user_id = uuid::Uuid::new_v4();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request", request_id = "abc-123", user_id = Empty);
+            let _request = request.enter();
+            record_user(user_id);
+            let _model = tracing::info_span!("Transaction::create").entered();
+            tracing::info!(status = 200, "request completed");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["message"], "request completed");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["span"]["name"], "Transaction::create");
+        // Enclosing spans are listed too, so nested events keep the request id.
+        assert_eq!(line["spans"][0]["request_id"], "abc-123");
+        assert_eq!(line["spans"][0]["user_id"], user_id.to_string());
+    }
+}
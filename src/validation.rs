@@ -0,0 +1,99 @@
+use actix_web::dev::Payload;
+use actix_web::{Error as ActixError, FromRequest, HttpRequest, web};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use std::ops::Deref;
+use validator::{Validate, ValidationError};
+
+use crate::error::AppError;
+use crate::models::user;
+
+/// Longest opaque token (refresh, reset, verification, MFA challenge) accepted in a body.
+pub const MAX_TOKEN_LENGTH: u64 = 256;
+/// Longest TOTP or recovery code accepted in a body.
+pub const MAX_CODE_LENGTH: u64 = 32;
+
+/// A JSON body that has been deserialized and then checked against its
+/// `#[validate(...)]` rules. Rule failures are reported per field.
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            value.validate().map_err(AppError::from)?;
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+// Field rules shared with the models, so a payload is held to the same
+// policy the model enforces.
+
+pub fn username(value: &str) -> Result<(), ValidationError> {
+    rule("username", user::validate_username(value))
+}
+
+/// Surrounding whitespace is ignored, as the models trim emails.
+pub fn email(value: &str) -> Result<(), ValidationError> {
+    rule("email", user::validate_email(value.trim()))
+}
+
+pub fn password(value: &str) -> Result<(), ValidationError> {
+    rule("password", user::validate_password(value))
+}
+
+fn rule(code: &'static str, result: Result<(), AppError>) -> Result<(), ValidationError> {
+    result.map_err(|err| ValidationError::new(code).with_message(err.detail().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize, Validate)]
+    struct SignUp {
+        #[validate(custom(function = "username"))]
+        username: String,
+        #[validate(custom(function = "email"))]
+        email: String,
+        #[validate(length(max = 10))]
+        description: Option<String>,
+    }
+
+    #[test]
+    fn test_rules_report_each_failing_field() {
+        let payload = SignUp {
+            username: "a b".into(),
+            email: " alice@example.com ".into(),
+            description: Some("x".repeat(11)),
+        };
+
+        let AppError::InvalidFields(errors) = AppError::from(payload.validate().unwrap_err())
+        else {
+            panic!("expected field errors");
+        };
+        let fields: Vec<(&str, &str)> =
+            errors.iter().map(|e| (e.field.as_str(), e.code.as_str())).collect();
+        assert_eq!(fields, vec![("description", "length"), ("username", "username")]);
+        assert!(errors[1].message.starts_with("Username must be"));
+    }
+}